@@ -0,0 +1,4 @@
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS search TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('english', author || ' ' || quote)) STORED;
+
+CREATE INDEX IF NOT EXISTS quotes_search_idx ON quotes USING GIN (search);
@@ -0,0 +1,39 @@
+CREATE TABLE IF NOT EXISTS quote_changes (
+    seq BIGSERIAL PRIMARY KEY,
+    quote_id UUID NOT NULL,
+    op TEXT NOT NULL,
+    version INT NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- record every change to `quotes` and announce it on the `quote_changes` channel
+CREATE OR REPLACE FUNCTION record_quote_change() RETURNS TRIGGER AS $$
+DECLARE
+    change quote_changes;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO quote_changes (quote_id, op, version)
+            VALUES (NEW.id, 'draft', NEW.version) RETURNING * INTO change;
+    ELSIF TG_OP = 'DELETE' THEN
+        INSERT INTO quote_changes (quote_id, op, version)
+            VALUES (OLD.id, 'purge', OLD.version) RETURNING * INTO change;
+    ELSIF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+        INSERT INTO quote_changes (quote_id, op, version)
+            VALUES (NEW.id, 'remove', NEW.version) RETURNING * INTO change;
+    ELSIF OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+        INSERT INTO quote_changes (quote_id, op, version)
+            VALUES (NEW.id, 'restore', NEW.version) RETURNING * INTO change;
+    ELSE
+        INSERT INTO quote_changes (quote_id, op, version)
+            VALUES (NEW.id, 'undo', NEW.version) RETURNING * INTO change;
+    END IF;
+
+    PERFORM pg_notify('quote_changes', row_to_json(change)::TEXT);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS quote_changes_trigger ON quotes;
+CREATE TRIGGER quote_changes_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON quotes
+    FOR EACH ROW EXECUTE FUNCTION record_quote_change();
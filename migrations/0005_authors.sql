@@ -0,0 +1,52 @@
+-- authors are matched ignoring case and surrounding or repeated whitespace
+CREATE OR REPLACE FUNCTION trim_author(name TEXT) RETURNS TEXT AS $$
+    SELECT regexp_replace(name, '^\s+|\s+$', '', 'g')
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION normalize_author(name TEXT) RETURNS TEXT AS $$
+    SELECT lower(regexp_replace(trim_author(name), '\s+', ' ', 'g'))
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE TABLE IF NOT EXISTS authors (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- display name of the first quote by this author
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS author_id UUID REFERENCES authors (id);
+
+-- linking existing quotes is not a change worth announcing
+ALTER TABLE quotes DISABLE TRIGGER quote_changes_trigger;
+
+INSERT INTO authors (name, normalized_name)
+    SELECT DISTINCT ON (normalize_author(author)) trim_author(author), normalize_author(author)
+    FROM quotes
+    ORDER BY normalize_author(author), created_at
+    ON CONFLICT (normalized_name) DO NOTHING;
+
+UPDATE quotes SET author_id = authors.id
+    FROM authors
+    WHERE authors.normalized_name = normalize_author(quotes.author) AND quotes.author_id IS NULL;
+
+ALTER TABLE quotes ENABLE TRIGGER quote_changes_trigger;
+
+ALTER TABLE quotes ALTER COLUMN author_id SET NOT NULL;
+CREATE INDEX IF NOT EXISTS quotes_author_id_idx ON quotes (author_id);
+
+-- link every new or re-authored quote, creating the author on first sight
+CREATE OR REPLACE FUNCTION resolve_quote_author() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO authors (name, normalized_name)
+        VALUES (trim_author(NEW.author), normalize_author(NEW.author))
+        ON CONFLICT (normalized_name) DO NOTHING;
+    SELECT id INTO NEW.author_id FROM authors WHERE normalized_name = normalize_author(NEW.author);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS quote_author_trigger ON quotes;
+CREATE TRIGGER quote_author_trigger
+    BEFORE INSERT OR UPDATE OF author ON quotes
+    FOR EACH ROW EXECUTE FUNCTION resolve_quote_author();
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS quote_audit (
+    id BIGSERIAL PRIMARY KEY,
+    -- NULL for actions on every quote, e.g. `clear`
+    quote_id UUID,
+    action TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    request_id TEXT,
+    ip TEXT,
+    before JSONB,
+    after JSONB,
+    at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS quote_audit_quote_id_idx ON quote_audit (quote_id);
+CREATE INDEX IF NOT EXISTS quote_audit_actor_idx ON quote_audit (actor);
+CREATE INDEX IF NOT EXISTS quote_audit_at_idx ON quote_audit (at);
+
+-- the audit log is append-only
+CREATE OR REPLACE FUNCTION reject_quote_audit_change() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'quote_audit is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS quote_audit_append_only ON quote_audit;
+CREATE TRIGGER quote_audit_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE ON quote_audit
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_quote_audit_change();
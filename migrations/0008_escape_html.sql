@@ -0,0 +1,5 @@
+-- search snippets are HTML, so quote text must be escaped before `<mark>` is added
+CREATE OR REPLACE FUNCTION escape_html(s TEXT) RETURNS TEXT AS $$
+    SELECT replace(replace(replace(replace(replace(s,
+        '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;')
+$$ LANGUAGE sql IMMUTABLE;
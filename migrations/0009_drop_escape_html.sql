@@ -0,0 +1,2 @@
+-- search snippets are highlighted over the raw quote text and escaped when rendered
+DROP FUNCTION IF EXISTS escape_html(TEXT);
@@ -0,0 +1,62 @@
+-- authors are matched as in Rust's `normalize_author`, whatever the database locale:
+-- whitespace is every `char::is_whitespace`, not the locale's `\s`, and only ASCII letters
+-- are case folded, since `lower()` folds the rest by locale if at all
+DO $$
+DECLARE
+    -- U+0085, U+00A0, U+1680, U+2000 to U+200A, U+2028, U+2029, U+202F, U+205F and U+3000,
+    -- from UTF-8 bytes as escapes need a UTF-8 database
+    space TEXT := '[\t\n\v\f\r ]|' || array_to_string(ARRAY(
+        SELECT convert_from(decode(hex, 'hex'), 'UTF8')
+        FROM unnest(ARRAY[
+            'c285', 'c2a0', 'e19a80',
+            'e28080', 'e28081', 'e28082', 'e28083', 'e28084', 'e28085',
+            'e28086', 'e28087', 'e28088', 'e28089', 'e2808a',
+            'e280a8', 'e280a9', 'e280af', 'e2819f', 'e38080'
+        ]) hex
+    ), '|');
+BEGIN
+    EXECUTE format($f$
+        CREATE OR REPLACE FUNCTION trim_author(name TEXT) RETURNS TEXT AS $b$
+            SELECT regexp_replace(name, %L, '', 'g')
+        $b$ LANGUAGE sql IMMUTABLE
+    $f$, '^(' || space || ')+|(' || space || ')+$');
+
+    EXECUTE format($f$
+        CREATE OR REPLACE FUNCTION normalize_author(name TEXT) RETURNS TEXT AS $b$
+            SELECT lower(regexp_replace(trim_author(name), %L, ' ', 'g') COLLATE "C")
+        $b$ LANGUAGE sql IMMUTABLE
+    $f$, '(' || space || ')+');
+END;
+$$;
+
+-- re-key existing authors: those now matching each other are merged into the first seen,
+-- quotes whose author no longer matches get one of their own, as in 0005_authors.sql
+CREATE TEMPORARY TABLE author_keys AS
+    SELECT id, normalize_author(name) AS key,
+        row_number() OVER (PARTITION BY normalize_author(name) ORDER BY created_at, id) AS rank
+    FROM authors;
+
+-- placeholders keep the keys unique while they change
+UPDATE authors SET normalized_name = id::TEXT;
+UPDATE authors SET name = trim_author(name), normalized_name = author_keys.key
+    FROM author_keys
+    WHERE author_keys.id = authors.id AND author_keys.rank = 1;
+
+INSERT INTO authors (name, normalized_name)
+    SELECT DISTINCT ON (normalize_author(author)) trim_author(author), normalize_author(author)
+    FROM quotes
+    WHERE NOT EXISTS (SELECT 1 FROM author_keys WHERE author_keys.key = normalize_author(quotes.author))
+    ORDER BY normalize_author(author), created_at;
+
+-- relinking quotes is not a change worth announcing
+ALTER TABLE quotes DISABLE TRIGGER quote_changes_trigger;
+
+UPDATE quotes SET author_id = authors.id
+    FROM authors
+    WHERE authors.normalized_name = normalize_author(quotes.author) AND quotes.author_id <> authors.id;
+
+ALTER TABLE quotes ENABLE TRIGGER quote_changes_trigger;
+
+DELETE FROM authors WHERE id IN (SELECT id FROM author_keys WHERE rank > 1);
+
+DROP TABLE author_keys;
@@ -0,0 +1,569 @@
+use std::{fmt, fs, io, net::{IpAddr, SocketAddr}, path::Path, str::FromStr, time::Duration};
+
+use axum::http::{HeaderValue, Uri};
+use serde::Deserialize;
+
+use crate::validation::QuoteLimits;
+
+/// Application settings, e.g. in `config.toml`:
+///
+/// ```toml
+/// seek_url = "https://www.youtube.com/watch?v=9Gc4QTqslN4"
+/// rng_seed = 2024
+///
+/// [milk]
+/// initial = 5
+/// max = 5
+/// ```
+///
+/// Every setting can be overridden by a Shuttle secret, then by an environment variable
+/// of the name given in `Config::overrides`, e.g. `GIFT_SECRET` or `QUOTE_STORE`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Target of the `/-1/seek` redirect
+    pub seek_url: String,
+    /// Seed of the Day 12 random boards, also used on reset
+    pub rng_seed: u64,
+    pub milk: MilkConfig,
+    pub gift: GiftConfig,
+    pub manifest: ManifestConfig,
+    pub quotes: QuotesConfig,
+    pub audit: AuditConfig,
+    pub lockfile: LockfileConfig,
+    pub themes: ThemesConfig,
+    pub server: ServerConfig,
+    pub log: LogConfig,
+}
+
+/// Shape of the Day 9 milk bucket
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MilkConfig {
+    pub initial: usize,
+    pub refill: usize,
+    pub max: usize,
+    pub interval_secs: u64,
+}
+
+/// Day 16 gift signing and Santa's key
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GiftConfig {
+    /// Signs and encrypts gifts and bearer tokens, best kept in `Secrets.toml`
+    pub secret: String,
+    pub santa_keys_dir: String,
+    pub santa_key_file: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestConfig {
+    /// Keyword a Day 5 manifest must list
+    pub keyword: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteStoreKind {
+    Postgres,
+    /// Quotes kept in process, e.g. for local debugging
+    Memory,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotesConfig {
+    pub store: QuoteStoreKind,
+    pub trash_retention_days: u64,
+    /// How long the change log keeps changes for `/19/changes` to resume from
+    pub change_retention_days: u64,
+    pub max_author_len: usize,
+    pub max_quote_len: usize,
+    /// Largest `/19/import` upload
+    pub import_max_bytes: usize,
+}
+
+/// Day 19 audit log
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Peers whose `X-Forwarded-For` is believed, e.g. a load balancer
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Bearer token required to read the audit log, which is closed if empty
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockfileConfig {
+    /// Largest lockfile accepted, after decompression
+    pub max_bytes: usize,
+    /// Checkout of the RustSec advisory database, audits are disabled if it cannot be loaded
+    pub advisory_db: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemesConfig {
+    pub file: String,
+}
+
+/// Standalone mode only, Shuttle binds and provisions the database itself
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    /// Postgres connection string, required in standalone mode
+    pub database_url: String,
+    /// How long in-flight requests may take to finish once asked to shut down
+    pub shutdown_grace_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    Human,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// `tracing_subscriber::EnvFilter` directives, e.g. `info,shuttlings_cch24=debug`
+    pub filter: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seek_url: "https://www.youtube.com/watch?v=9Gc4QTqslN4".to_owned(),
+            rng_seed: 2024,
+            milk: MilkConfig::default(),
+            gift: GiftConfig::default(),
+            manifest: ManifestConfig::default(),
+            quotes: QuotesConfig::default(),
+            audit: AuditConfig::default(),
+            lockfile: LockfileConfig::default(),
+            themes: ThemesConfig::default(),
+            server: ServerConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+impl Default for MilkConfig {
+    fn default() -> Self {
+        Self {
+            initial: 5,
+            refill: 1,
+            max: 5,
+            interval_secs: 1,
+        }
+    }
+}
+
+impl Default for GiftConfig {
+    /// No secret, it has to be configured
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            santa_keys_dir: "keys".to_owned(),
+            santa_key_file: "day16_santa_public_key.pem".to_owned(),
+        }
+    }
+}
+
+impl fmt::Debug for GiftConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GiftConfig")
+            .field("secret", &"..")
+            .field("santa_keys_dir", &self.santa_keys_dir)
+            .field("santa_key_file", &self.santa_key_file)
+            .finish()
+    }
+}
+
+impl Default for ManifestConfig {
+    fn default() -> Self {
+        Self { keyword: "Christmas 2024".to_owned() }
+    }
+}
+
+impl Default for QuotesConfig {
+    fn default() -> Self {
+        let limits = QuoteLimits::default();
+        Self {
+            store: QuoteStoreKind::Postgres,
+            trash_retention_days: 30,
+            change_retention_days: 7,
+            max_author_len: limits.max_author_len,
+            max_quote_len: limits.max_quote_len,
+            import_max_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+impl fmt::Debug for AuditConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditConfig")
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("token", &"..")
+            .finish()
+    }
+}
+
+impl Default for LockfileConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 2 * 1024 * 1024,
+            advisory_db: "advisory-db".to_owned(),
+        }
+    }
+}
+
+impl Default for ThemesConfig {
+    fn default() -> Self {
+        Self { file: "themes.toml".to_owned() }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8000)),
+            database_url: String::new(),
+            shutdown_grace_secs: 30,
+        }
+    }
+}
+
+impl fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("bind", &self.bind)
+            .field("database_url", &"..")
+            .field("shutdown_grace_secs", &self.shutdown_grace_secs)
+            .finish()
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Human,
+            filter: "info".to_owned(),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format {:?}", other)),
+        }
+    }
+}
+
+impl FromStr for QuoteStoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(Self::Postgres),
+            "memory" => Ok(Self::Memory),
+            other => Err(format!("unknown quote store {:?}", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    /// A setting or its override is unusable
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "cannot read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "cannot parse config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Replace `field` with the parsed value of `name`, if set
+fn set<T>(field: &mut T, name: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Some(value) = lookup(name) {
+        *field = value.trim().parse()
+            .map_err(|e| ConfigError::Invalid(format!("{}: {}", name, e)))?;
+    };
+    Ok(())
+}
+
+/// Replace `field` with the parsed comma-separated values of `name`, if set
+fn set_list<T>(field: &mut Vec<T>, name: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if let Some(value) = lookup(name) {
+        *field = value.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().map_err(|e| ConfigError::Invalid(format!("{}: {}", name, e))))
+            .collect::<Result<_, _>>()?;
+    };
+    Ok(())
+}
+
+impl Config {
+    /// Read the TOML file at `path`, all defaults if it does not exist,
+    /// then apply Shuttle secrets and environment variables on top and validate the result
+    pub fn load(
+        path: impl AsRef<Path>,
+        secrets: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError>
+    {
+        Self::load_from(path, secrets, |name| std::env::var(name).ok())
+    }
+
+    /// `load` with the environment looked up by `env`
+    fn load_from(
+        path: impl AsRef<Path>,
+        secrets: impl Fn(&str) -> Option<String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError>
+    {
+        let mut config = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(ConfigError::Parse)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(ConfigError::Read(e)),
+        };
+        config.overrides(&secrets)?;
+        config.overrides(&env)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Apply the values `lookup` has for the override names
+    pub fn overrides(&mut self, lookup: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        set(&mut self.seek_url, "SEEK_URL", lookup)?;
+        set(&mut self.rng_seed, "RNG_SEED", lookup)?;
+        set(&mut self.milk.initial, "MILK_INITIAL", lookup)?;
+        set(&mut self.milk.refill, "MILK_REFILL", lookup)?;
+        set(&mut self.milk.max, "MILK_MAX", lookup)?;
+        set(&mut self.milk.interval_secs, "MILK_INTERVAL_SECS", lookup)?;
+        set(&mut self.gift.secret, "GIFT_SECRET", lookup)?;
+        set(&mut self.gift.santa_keys_dir, "SANTA_KEYS_DIR", lookup)?;
+        set(&mut self.gift.santa_key_file, "SANTA_KEY_FILE", lookup)?;
+        set(&mut self.manifest.keyword, "MANIFEST_KEYWORD", lookup)?;
+        set(&mut self.quotes.store, "QUOTE_STORE", lookup)?;
+        set(&mut self.quotes.trash_retention_days, "QUOTE_TRASH_RETENTION_DAYS", lookup)?;
+        set(&mut self.quotes.change_retention_days, "QUOTE_CHANGE_RETENTION_DAYS", lookup)?;
+        set(&mut self.quotes.max_author_len, "QUOTE_MAX_AUTHOR_LEN", lookup)?;
+        set(&mut self.quotes.max_quote_len, "QUOTE_MAX_QUOTE_LEN", lookup)?;
+        set(&mut self.quotes.import_max_bytes, "QUOTE_IMPORT_MAX_BYTES", lookup)?;
+        set_list(&mut self.audit.trusted_proxies, "TRUSTED_PROXIES", lookup)?;
+        set(&mut self.audit.token, "AUDIT_TOKEN", lookup)?;
+        set(&mut self.lockfile.max_bytes, "LOCKFILE_MAX_BYTES", lookup)?;
+        set(&mut self.lockfile.advisory_db, "RUSTSEC_DB", lookup)?;
+        set(&mut self.themes.file, "THEMES_FILE", lookup)?;
+        set(&mut self.server.bind, "BIND_ADDRESS", lookup)?;
+        set(&mut self.server.database_url, "DATABASE_URL", lookup)?;
+        set(&mut self.server.shutdown_grace_secs, "SHUTDOWN_GRACE_SECS", lookup)?;
+        set(&mut self.log.format, "LOG_FORMAT", lookup)?;
+        set(&mut self.log.filter, "RUST_LOG", lookup)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: &str| Err(ConfigError::Invalid(e.to_owned()));
+
+        let seek = self.seek_url.parse::<Uri>().ok();
+        if seek.as_ref().and_then(Uri::scheme).is_none() || HeaderValue::from_str(&self.seek_url).is_err() {
+            return invalid("seek_url must be an absolute URL");
+        };
+        if self.milk.max == 0 || self.milk.initial > self.milk.max {
+            return invalid("milk.max must be positive and at least milk.initial");
+        };
+        if self.milk.refill == 0 || self.milk.interval_secs == 0 {
+            return invalid("milk.refill and milk.interval_secs must be positive");
+        };
+        if self.gift.secret.is_empty() {
+            return invalid("gift.secret must be set, e.g. as GIFT_SECRET in Secrets.toml");
+        };
+        if self.manifest.keyword.trim().is_empty() {
+            return invalid("manifest.keyword must not be empty");
+        };
+        if self.quotes.max_author_len == 0 || self.quotes.max_quote_len == 0 {
+            return invalid("quotes.max_author_len and quotes.max_quote_len must be positive");
+        };
+        if self.quotes.import_max_bytes == 0 {
+            return invalid("quotes.import_max_bytes must be positive");
+        };
+        if self.lockfile.max_bytes == 0 {
+            return invalid("lockfile.max_bytes must be positive");
+        };
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            return Err(ConfigError::Invalid(format!("log.filter: {}", e)));
+        };
+        Ok(())
+    }
+
+    pub fn quote_limits(&self) -> QuoteLimits {
+        QuoteLimits {
+            max_author_len: self.quotes.max_author_len,
+            max_quote_len: self.quotes.max_quote_len,
+            ..QuoteLimits::default()
+        }
+    }
+}
+
+impl MilkConfig {
+    /// A full bucket of this shape
+    pub fn bucket(&self) -> leaky_bucket::RateLimiter {
+        leaky_bucket::RateLimiter::builder()
+            .initial(self.initial)
+            .refill(self.refill)
+            .max(self.max)
+            .interval(Duration::from_secs(self.interval_secs))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Lookup of the given `(name, value)` pairs only
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.gift.secret = "hunter2".to_owned();
+        config
+    }
+
+    fn assert_invalid(config: Config, message: &str) {
+        match config.validate() {
+            Err(ConfigError::Invalid(e)) => assert!(e.contains(message), "{:?} does not mention {:?}", e, message),
+            other => panic!("{:?} accepted, expected {:?}", other, message),
+        };
+    }
+
+    #[test]
+    fn parses_toml() {
+        let config: Config = toml::from_str(r#"
+            seek_url = "https://example.com/"
+            rng_seed = 7
+
+            [milk]
+            max = 10
+
+            [quotes]
+            store = "memory"
+
+            [audit]
+            trusted_proxies = ["10.0.0.1", "::1"]
+        "#).unwrap();
+        assert_eq!((config.seek_url.as_str(), config.rng_seed), ("https://example.com/", 7));
+        // unset fields of a table keep their defaults
+        assert_eq!((config.milk.initial, config.milk.max), (5, 10));
+        assert_eq!(config.quotes.store, QuoteStoreKind::Memory);
+        assert_eq!(config.quotes.trash_retention_days, 30);
+        assert_eq!(config.audit.trusted_proxies, ["10.0.0.1".parse::<IpAddr>().unwrap(), "::1".parse().unwrap()]);
+
+        // the checked-in file needs only the secret
+        let mut config = Config::load_from(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml"),
+            vars(&[("GIFT_SECRET", "hunter2")]),
+            vars(&[]),
+        ).unwrap();
+        config.gift.secret = String::new();
+        assert_eq!(format!("{:?}", config), format!("{:?}", Config::default()));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        for toml in ["seek = \"https://example.com/\"", "[milk]\nmaximum = 5", "[audit]\ntokens = \"x\"", "[database]\nurl = \"x\""] {
+            let e = toml::from_str::<Config>(toml).unwrap_err();
+            assert!(e.message().contains("unknown field"), "{}: {}", toml, e);
+        };
+        assert!(matches!(
+            Config::load_from(concat!(env!("CARGO_MANIFEST_DIR"), "/themes.toml"), vars(&[]), vars(&[])),
+            Err(ConfigError::Parse(_)),
+        ));
+    }
+
+    #[test]
+    fn secrets_then_env() {
+        let secrets = vars(&[
+            ("GIFT_SECRET", "from secrets"),
+            ("RNG_SEED", "1"),
+            ("QUOTE_STORE", "memory"),
+        ]);
+        let env = vars(&[
+            ("RNG_SEED", " 2 "),
+            ("TRUSTED_PROXIES", "10.0.0.1, 10.0.0.2,"),
+        ]);
+        let config = Config::load_from("no/such/config.toml", secrets, env).unwrap();
+        assert_eq!(config.gift.secret, "from secrets");
+        assert_eq!(config.rng_seed, 2);
+        assert_eq!(config.quotes.store, QuoteStoreKind::Memory);
+        assert_eq!(config.audit.trusted_proxies.len(), 2);
+        assert_eq!(config.seek_url, Config::default().seek_url);
+
+        let bad = Config::load_from("no/such/config.toml", vars(&[("GIFT_SECRET", "x")]), vars(&[("MILK_MAX", "lots")]));
+        assert!(matches!(bad, Err(ConfigError::Invalid(e)) if e.starts_with("MILK_MAX")));
+        // validated after every override
+        let unset = Config::load_from("no/such/config.toml", vars(&[]), vars(&[]));
+        assert!(matches!(unset, Err(ConfigError::Invalid(e)) if e.contains("gift.secret")));
+    }
+
+    #[test]
+    fn validate_rejects() {
+        assert!(valid().validate().is_ok());
+
+        type Change = fn(&mut Config);
+        let cases: [(Change, &str); 11] = [
+            (|c| c.seek_url = "/relative".to_owned(), "seek_url"),
+            (|c| c.seek_url = "https://example.com/\n".to_owned(), "seek_url"),
+            (|c| c.milk.max = 0, "milk.max"),
+            (|c| c.milk.initial = 6, "milk.max"),
+            (|c| c.milk.refill = 0, "milk.refill"),
+            (|c| c.gift.secret = String::new(), "gift.secret"),
+            (|c| c.manifest.keyword = " ".to_owned(), "manifest.keyword"),
+            (|c| c.quotes.max_quote_len = 0, "quotes.max_author_len"),
+            (|c| c.quotes.import_max_bytes = 0, "quotes.import_max_bytes"),
+            (|c| c.lockfile.max_bytes = 0, "lockfile.max_bytes"),
+            (|c| c.log.filter = "info,[".to_owned(), "log.filter"),
+        ];
+        for (change, message) in cases {
+            let mut config = valid();
+            change(&mut config);
+            assert_invalid(config, message);
+        };
+    }
+}
@@ -1,7 +1,9 @@
-use lazy_static::lazy_static;
+use askama::Template;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::sync::{Arc, Mutex};
-use axum::{extract::{State, Path}, response::{Response, IntoResponse}, http::StatusCode};
+use axum::{extract::{State, Path}, response::{Response, IntoResponse}, http::{HeaderMap, StatusCode}};
+
+use crate::{config::Config, templates::HtmlTemplate};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Tile {
@@ -20,7 +22,7 @@ impl std::fmt::Display for Tile {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Board {
     b: [Vec<Tile>; 4], // each vec a *column* not *row*
     winner: Option<Tile>,
@@ -49,10 +51,7 @@ impl std::fmt::Display for Board {
             self[(1, 0)], self[(1, 1)], self[(1, 2)], self[(1, 3)],
             self[(2, 0)], self[(2, 1)], self[(2, 2)], self[(2, 3)],
             self[(3, 0)], self[(3, 1)], self[(3, 2)], self[(3, 3)],
-            match self.winning_message() {
-                Some(s) => s,
-                None => String::new(),
-            },
+            self.winning_message().unwrap_or_default(),
         )
     }
 }
@@ -115,6 +114,11 @@ impl Board {
         };
     }
 
+    /// Tiles row by row, top row first
+    fn rows(&self) -> [[Tile; 4]; 4] {
+        std::array::from_fn(|i| std::array::from_fn(|j| self[(i, j)]))
+    }
+
     fn reset(&mut self) {
         self.b = Default::default();
         self.winner = None;
@@ -122,9 +126,9 @@ impl Board {
 
     fn winning_message(&self) -> Option<String> {
         self.winner
-            .and_then(|w| match w {
-                    Tile::Empty => Some("No winner.\n".to_owned()),
-                    other => Some(format!("{} wins!\n", other)),
+            .map(|w| match w {
+                    Tile::Empty => "No winner.\n".to_owned(),
+                    other => format!("{} wins!\n", other),
         })
     }
 
@@ -161,28 +165,77 @@ impl Board {
     }
 }
 
-lazy_static! {
-    pub static ref singleton_board: Arc<Mutex<Board>> = Arc::new(Mutex::new(Board::new()));
+/// Grid with a button per team above each column, tiles shown with `Tile`'s `Display`
+#[derive(Template)]
+#[template(path = "day_12/board.html")]
+struct BoardTemplate {
+    rows: [[Tile; 4]; 4],
+    message: Option<String>,
+    cookie: Tile,
+    milk: Tile,
+}
+
+impl BoardTemplate {
+    fn new(b: &Board) -> Self {
+        Self {
+            rows: b.rows(),
+            message: b.winning_message(),
+            cookie: Tile::Cookie,
+            milk: Tile::Milk,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "day_12/page.html")]
+struct PageTemplate {
+    board: BoardTemplate,
+}
+
+/// Page to play on the board
+pub async fn play(State(b): State<Arc<Mutex<Board>>>) -> impl IntoResponse {
+    let b = b.lock().unwrap();
+    HtmlTemplate(PageTemplate { board: BoardTemplate::new(&b) })
+}
+
+/// The board as an HTML fragment for htmx requests, as text otherwise
+fn render_board(b: &Board, headers: &HeaderMap) -> Response {
+    if headers.contains_key("HX-Request") {
+        HtmlTemplate(BoardTemplate::new(b)).into_response()
+    } else {
+        b.to_string().into_response()
+    }
 }
 
-pub async fn board(State(b): State<Arc<Mutex<Board>>>) -> impl IntoResponse {
+pub async fn board(
+    State(b): State<Arc<Mutex<Board>>>,
+    headers: HeaderMap,
+) -> Response
+{
     let b = b.lock().unwrap();
-    b.to_string()
+    render_board(&b, &headers)
 }
 
-pub async fn reset(State((b, rng)): State<(Arc<Mutex<Board>>, Arc<Mutex<StdRng>>)>) -> impl IntoResponse {
+pub async fn reset(
+    State(b): State<Arc<Mutex<Board>>>,
+    State(rng): State<Arc<Mutex<StdRng>>>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+) -> Response
+{
     let mut b = b.lock().unwrap();
     b.reset();
 
     let mut rng = rng.lock().unwrap();
-    *rng = rand::rngs::StdRng::seed_from_u64(2024);
+    *rng = rand::rngs::StdRng::seed_from_u64(config.rng_seed);
 
-    b.to_string()
+    render_board(&b, &headers)
 }
 
 pub async fn place(
     Path((team, col)): Path<(String, usize)>,
     State(b): State<Arc<Mutex<Board>>>,
+    headers: HeaderMap,
 ) -> Response
 {
     // validate data
@@ -206,19 +259,29 @@ pub async fn place(
     // insert tile
     let mut b = b.lock().unwrap();
     if !b.insert(col - 1, team) {
+        tracing::debug!(%team, column = col, "tile rejected, column full or game over");
         (
             StatusCode::SERVICE_UNAVAILABLE,
-            b.to_string(),
+            render_board(&b, &headers),
         ).into_response()
     } else {
+        match b.winner {
+            Some(Tile::Empty) => tracing::info!("board full, no winner"),
+            Some(winner) => tracing::info!(%winner, "board won"),
+            None => {},
+        };
         (
             StatusCode::OK,
-            b.to_string(),
+            render_board(&b, &headers),
         ).into_response()
     }
 }
 
-pub async fn random_board(State(rng): State<Arc<Mutex<StdRng>>>) -> String {
+pub async fn random_board(
+    State(rng): State<Arc<Mutex<StdRng>>>,
+    headers: HeaderMap,
+) -> Response
+{
     let mut rng = rng.lock().unwrap();
-    Board::new_random(&mut *rng).to_string()
+    render_board(&Board::new_random(&mut rng), &headers)
 }
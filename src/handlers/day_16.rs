@@ -1,18 +1,107 @@
-use std::{collections::HashSet, fs};
+use std::{collections::HashSet, error::Error, fs, path::{Path, PathBuf}, sync::{Arc, OnceLock}};
 
-use axum::{http::{header, HeaderMap, StatusCode}, response::IntoResponse, extract::Json};
+use aes_gcm::{aead::AeadInPlace, Aes256Gcm, KeyInit, Nonce, Tag};
+use arc_swap::ArcSwap;
+use axum::{http::{header, HeaderMap, StatusCode}, response::IntoResponse, extract::{Json, Query, State}};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rand::Rng;
+use serde::Deserialize;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
-static SECRET: &str = "lingang guli guli guli grata lingangu lingangu";
+use crate::config::Config;
 
-pub async fn wrap(Json(payload): Json<Value>) -> impl IntoResponse {
+/// Protected header of encrypted gifts, the payload is a signed gift (nested JWT)
+static JWE_HEADER: &str = r#"{"alg":"dir","enc":"A256GCM","cty":"JWT"}"#;
+
+/// Santa's public key, loaded once and swapped atomically when the key directory changes
+pub struct SantaKeys {
+    dir: PathBuf,
+    file: PathBuf,
+    key: ArcSwap<DecodingKey>,
+    watcher: OnceLock<RecommendedWatcher>,
+}
+
+impl SantaKeys {
+    /// Load and validate the key `file` in `dir`, fail if it is missing or malformed
+    pub fn load(dir: impl Into<PathBuf>, file: impl Into<PathBuf>) -> Result<Arc<Self>, Box<dyn Error + Send + Sync>> {
+        let dir = dir.into();
+        let file = file.into();
+        let key = Self::read_key(&dir.join(&file))?;
+
+        Ok(Arc::new(Self {
+            dir,
+            file,
+            key: ArcSwap::from_pointee(key),
+            watcher: OnceLock::new(),
+        }))
+    }
+
+    fn read_key(path: &Path) -> Result<DecodingKey, Box<dyn Error + Send + Sync>> {
+        let pem = fs::read_to_string(path)?;
+        Ok(DecodingKey::from_rsa_pem(pem.as_bytes())?)
+    }
+
+    /// Re-read the key, keep the current one if the new one is invalid
+    pub fn reload(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let key = Self::read_key(&self.dir.join(&self.file))?;
+        self.key.store(Arc::new(key));
+        Ok(())
+    }
+
+    /// Reload the key whenever something in the key directory changes
+    pub fn watch(self: &Arc<Self>) -> notify::Result<()> {
+        // weak reference so the watcher stored in `self` does not keep `self` alive
+        let keys = Arc::downgrade(self);
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Some(keys) = keys.upgrade() else { return; };
+
+            match res {
+                Ok(event) if event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove() => {
+                    match keys.reload() {
+                        Ok(()) => tracing::info!("reloaded Day 16 keys"),
+                        Err(e) => tracing::warn!(error = %e, "failed to reload Day 16 keys, keeping previous"),
+                    };
+                },
+                Ok(_) => {},
+                Err(e) => tracing::error!(error = %e, "Day 16 key watcher error"),
+            };
+        })?;
+        watcher.watch(&self.dir, RecursiveMode::NonRecursive)?;
+
+        // a second call keeps the first watcher
+        let _ = self.watcher.set(watcher);
+        Ok(())
+    }
+
+    pub fn current(&self) -> Arc<DecodingKey> {
+        self.key.load_full()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WrapParams {
+    #[serde(default)]
+    encrypt: bool,
+}
+
+pub async fn wrap(
+    State(config): State<Arc<Config>>,
+    Query(params): Query<WrapParams>,
+    Json(payload): Json<Value>,
+) -> impl IntoResponse
+{
     let mut gift = String::from("gift=");
-    let token = jsonwebtoken::encode(
+    let mut token = jsonwebtoken::encode(
         &Header::default(),
         &payload,
-        &EncodingKey::from_secret(SECRET.as_ref())
+        &EncodingKey::from_secret(config.gift.secret.as_ref())
     ).unwrap();
+    if params.encrypt {
+        token = encrypt_gift(&config.gift.secret, &token);
+    };
     gift.push_str(token.as_str());
 
     (
@@ -21,32 +110,111 @@ pub async fn wrap(Json(payload): Json<Value>) -> impl IntoResponse {
     )
 }
 
-pub async fn unwrap(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
-    let validation = &mut Validation::default();
-    validation.required_spec_claims = HashSet::new();
-    
-    if let Some(gift) = headers.get("Cookie")
+pub async fn unwrap(
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode>
+{
+    let secret = &config.gift.secret;
+    let Some(token) = headers.get("Cookie")
         .and_then(|v| v.to_str().ok())
         .and_then(|s| s.strip_prefix("gift="))
-        .and_then(|t| jsonwebtoken::decode::<Value>(t,
-            &DecodingKey::from_secret(SECRET.as_ref()),
-            validation).ok())
-        .and_then(|d| Some(d.claims))
-    {
-        Ok(Json(gift))
+    else {
+        tracing::debug!("no gift cookie");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // JWE compact serialization has five parts, JWS has three
+    let token = if token.split('.').count() == 5 {
+        decrypt_gift(secret, token).ok_or_else(|| {
+            tracing::info!("gift decryption failed");
+            StatusCode::BAD_REQUEST
+        })?
     } else {
-        Err(StatusCode::BAD_REQUEST)
-    }
+        token.to_owned()
+    };
+
+    verify_token(secret, &token)
+        .map(Json)
+        .ok_or(StatusCode::BAD_REQUEST)
+}
+
+/// Claims of a token signed with `secret`, `None` if the signature or expiry is invalid
+pub fn verify_token(secret: &str, token: &str) -> Option<Value> {
+    let validation = &mut Validation::default();
+    validation.required_spec_claims = HashSet::new();
+
+    jsonwebtoken::decode::<Value>(token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        validation)
+        .inspect_err(|e| tracing::info!(reason = ?e.kind(), "token rejected"))
+        .ok()
+        .map(|d| d.claims)
+}
+
+/// Content encryption key for `dir` + A256GCM, derived from the signing secret
+fn gift_cipher(secret: &str) -> Aes256Gcm {
+    let key = Sha256::new()
+        .chain_update(b"gift encryption key\0")
+        .chain_update(secret)
+        .finalize();
+    Aes256Gcm::new(&key)
 }
 
-pub async fn decode(token: String) -> Result<Json<Value>, StatusCode> {
-    let key = DecodingKey::from_rsa_pem(
-        fs::read_to_string("keys/day16_santa_public_key.pem")
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-            .as_bytes()
+/// Wrap a signed gift into a JWE (compact serialization)
+fn encrypt_gift(secret: &str, jws: &str) -> String {
+    let header = URL_SAFE_NO_PAD.encode(JWE_HEADER);
+    let mut iv = [0u8; 12];
+    rand::thread_rng().fill(&mut iv);
+
+    let mut ciphertext = jws.as_bytes().to_vec();
+    // header is the additional authenticated data
+    let tag = gift_cipher(secret)
+        .encrypt_in_place_detached(Nonce::from_slice(&iv), header.as_bytes(), &mut ciphertext)
+        .unwrap();
+
+    format!("{}..{}.{}.{}",
+        header,
+        URL_SAFE_NO_PAD.encode(iv),
+        URL_SAFE_NO_PAD.encode(ciphertext),
+        URL_SAFE_NO_PAD.encode(tag),
     )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
+}
+
+/// Unwrap a JWE produced by `encrypt_gift`, returning the signed gift inside
+fn decrypt_gift(secret: &str, jwe: &str) -> Option<String> {
+    let parts: Vec<&str> = jwe.split('.').collect();
+    let [header, encrypted_key, iv, ciphertext, tag] = parts[..] else { return None; };
+
+    // `dir` carries no encrypted key
+    if !encrypted_key.is_empty() {
+        return None;
+    };
+    let protected: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    if protected.get("alg")? != "dir" || protected.get("enc")? != "A256GCM" {
+        return None;
+    };
+
+    let iv = URL_SAFE_NO_PAD.decode(iv).ok()?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+    if iv.len() != 12 || tag.len() != 16 {
+        return None;
+    };
+    let mut plaintext = URL_SAFE_NO_PAD.decode(ciphertext).ok()?;
+
+    gift_cipher(secret)
+        .decrypt_in_place_detached(Nonce::from_slice(&iv), header.as_bytes(), &mut plaintext, Tag::from_slice(&tag))
+        .ok()?;
+    String::from_utf8(plaintext).ok()
+}
+
+pub async fn decode(
+    State(keys): State<Arc<SantaKeys>>,
+    token: String,
+) -> Result<Json<Value>, StatusCode>
+{
+    let key = keys.current();
+
     let validation = &mut Validation::default();
     validation.required_spec_claims = HashSet::new();
     validation.algorithms = vec![jsonwebtoken::Algorithm::RS256, jsonwebtoken::Algorithm::RS512];
@@ -56,6 +224,7 @@ pub async fn decode(token: String) -> Result<Json<Value>, StatusCode> {
         &key,
         validation,
     )
+    .inspect_err(|e| tracing::info!(reason = ?e.kind(), "Santa's token rejected"))
     .map_err(|e| match e.kind() {
         jsonwebtoken::errors::ErrorKind::InvalidSignature => StatusCode::UNAUTHORIZED,
         jsonwebtoken::errors::ErrorKind::InvalidEcdsaKey => StatusCode::UNAUTHORIZED,
@@ -1,112 +1,1252 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, convert::Infallible, error::Error, io, net::{IpAddr, SocketAddr}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
 
-use axum::{extract::{State, Path, Json}, http::StatusCode};
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, FromRef, FromRequestParts, State, Path, Json, Query},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+};
+use futures::{Stream, StreamExt, TryStreamExt};
+use http_body_util::{LengthLimitError, Limited};
 use serde::{Deserialize, Serialize};
-use sqlx::{types::Uuid, PgPool};
+use rand::Rng;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::types::{chrono::{DateTime, Local, NaiveDate}, Uuid};
+use tokio::{io::AsyncBufReadExt, sync::broadcast};
+use tokio_util::io::StreamReader;
 
-use crate::models::Quote;
+use super::day_16::verify_token;
+use crate::{
+    config::Config,
+    models::{AuditContext, AuditEntry, Author, Quote, QuoteChange, QuoteHit, Tag},
+    store::{AuditFilter, ImportedQuote, QuoteFilter, QuoteStore, SearchQuery, StoreError, TagFilter},
+    templates::highlight_html,
+    validation::{validate_quote, QuoteLimits, ValidationErrors},
+};
 
-/// Clear the `quotes` table
-pub async fn clear_quotes(
-    State(pool): State<Arc<PgPool>>,
+#[derive(Debug, Deserialize)]
+pub struct ClearParams {
+    #[serde(default)]
+    hard: bool,
+}
+
+/// Client address: the peer, or if it is a trusted proxy, the last `X-Forwarded-For` address
+/// not added by a trusted proxy
+fn client_ip(peer: Option<IpAddr>, forwarded_for: Option<&str>, trusted: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    };
+
+    let Some(chain) = forwarded_for else {
+        return Some(peer);
+    };
+    let chain: Option<Vec<IpAddr>> = chain.split(',').map(|a| a.trim().parse().ok()).collect();
+    let Some(chain) = chain else {
+        return Some(peer);
+    };
+    // proxies append the address they got the request from
+    chain.iter()
+        .rev()
+        .find(|a| !trusted.contains(a))
+        .or(chain.first())
+        .copied()
+        .or(Some(peer))
+}
+
+/// Caller of a quote mutation: `sub` claim of a bearer token signed with our secret,
+/// `X-Request-Id`, and the client address as given by `client_ip`
+#[async_trait]
+impl<T> FromRequestParts<T> for AuditContext
+where
+    T: Send + Sync,
+    Arc<Config>: FromRef<T>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &T) -> Result<Self, Self::Rejection> {
+        let config = Arc::<Config>::from_ref(state);
+        let header = |name| parts.headers.get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+
+        let actor = header(header::AUTHORIZATION.as_str())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|t| verify_token(&config.gift.secret, t.trim()))
+            .and_then(|claims| claims.get("sub")?.as_str().map(str::to_owned));
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip());
+        let ip = client_ip(peer, header("x-forwarded-for"), &config.audit.trusted_proxies)
+            .map(|ip| ip.to_string());
+
+        Ok(AuditContext {
+            actor: actor.unwrap_or_else(|| AuditContext::default().actor),
+            request_id: header("x-request-id").map(str::to_owned),
+            ip,
+        })
+    }
+}
+
+fn store_status(e: StoreError) -> StatusCode {
+    match e {
+        StoreError::NotFound => StatusCode::NOT_FOUND,
+        StoreError::Conflict => StatusCode::PRECONDITION_FAILED,
+        StoreError::Aborted => StatusCode::BAD_REQUEST,
+        StoreError::Backend(e) => {
+            tracing::error!(error = %e, "quote store error");
+            StatusCode::INTERNAL_SERVER_ERROR
+        },
+    }
+}
+
+/// Clear the `quotes` table, moving everything to the trash unless `hard` is set
+pub async fn clear_quotes<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    ctx: AuditContext,
+    Query(params): Query<ClearParams>,
 ) -> Result<StatusCode, StatusCode>
 {   
-    match sqlx::query("DELETE FROM quotes")
-        .execute(&*pool)
-        .await
-    {
+    match store.clear(&ctx, params.hard).await {
         Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(store_status(e)),
     }
 }
 
 // Get quote by ID
-pub async fn cite(
-    State(pool): State<Arc<PgPool>>,
+pub async fn cite<S: QuoteStore>(
+    State(store): State<Arc<S>>,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Quote>, StatusCode>
 {
-    match sqlx::query_as::<_, Quote>("SELECT * FROM quotes WHERE id = $1")
-        .bind(id)
-        .fetch_one(&*pool)
-        .await
-    {
-        Ok(q) => Ok(Json(q)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
-    }
+    store.get(id).await
+        .map(Json)
+        .map_err(store_status)
 }
 
-// Delete quote with givin ID, respond with content
-pub async fn remove(
-    State(pool): State<Arc<PgPool>>,
+// Move quote with givin ID to the trash, respond with content
+pub async fn remove<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    ctx: AuditContext,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Quote>, StatusCode>
 {
-    // use transaction for atomicity
-    let mut tx = pool.begin().await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let q = sqlx::query_as::<_, Quote>("SELECT * FROM quotes WHERE id = $1")
-        .bind(id)
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    match sqlx::query("DELETE FROM quotes WHERE id = $1")
-        .bind(id)
-        .execute(&mut *tx)
-        .await
-    {
-        Ok(_) => {
-            tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            Ok(Json(q))
-        },
-        Err(_) => {
-            // not commit transaction if errors are met
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    store.delete(&ctx, id).await
+        .map(Json)
+        .map_err(store_status)
+}
+
+/// List quotes in the trash, most recently deleted first
+pub async fn trash<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+) -> Result<Json<Vec<Quote>>, StatusCode>
+{
+    store.trash().await
+        .map(Json)
+        .map_err(store_status)
+}
+
+/// Take a quote out of the trash
+pub async fn restore<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Quote>, StatusCode>
+{
+    store.restore(id).await
+        .map(Json)
+        .map_err(store_status)
+}
+
+/// Periodically delete quotes that have been in the trash for longer than `retention`
+pub fn spawn_trash_purge<S: QuoteStore>(store: Arc<S>, retention: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = store.purge_trash(retention).await {
+                tracing::error!(error = %e, "failed to purge quote trash");
+            };
+        };
+    });
+}
+
+/// Periodically drop changes older than `retention` from the change log
+pub fn spawn_change_purge<S: QuoteStore>(store: Arc<S>, retention: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = store.purge_changes(retention).await {
+                tracing::error!(error = %e, "failed to purge quote changes");
+            };
+        };
+    });
 }
 
 /// Update a record with givin ID
-pub async fn undo(
-    State(pool): State<Arc<PgPool>>,
+pub async fn undo<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    State(limits): State<Arc<QuoteLimits>>,
+    ctx: AuditContext,
     Path(id): Path<Uuid>,
     Json(req): Json<QuoteReq>,
-) -> Result<Json<Quote>, StatusCode>
+) -> Result<Json<Quote>, Response>
 {
-    match sqlx::query_as::<_, Quote>("UPDATE quotes SET author = $1, quote = $2, version = version + 1 WHERE id = $3 RETURNING *")
-        .bind(req.author)
-        .bind(req.quote)
-        .bind(id)
-        .fetch_one(&*pool)
-        .await
-    {
-        Ok(q) => Ok(Json(q)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
-    }
+    let req = req.trimmed();
+    validate_quote(&limits, &req.author, &req.quote, req.tags.as_deref())
+        .map_err(IntoResponse::into_response)?;
+
+    store.update(&ctx, id, None, req.author, req.quote, req.tags).await
+        .map(Json)
+        .map_err(|e| store_status(e).into_response())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QuoteReq {
     pub author: String,
     pub quote: String,
+    /// Replaces the tags of the quote, `undo` keeps them if absent
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+}
+
+impl QuoteReq {
+    /// Without surrounding whitespace, as stored
+    fn trimmed(self) -> Self {
+        Self {
+            author: self.author.trim().to_owned(),
+            quote: self.quote.trim().to_owned(),
+            tags: self.tags,
+        }
+    }
 }
 
 /// Create new record
-pub async fn draft(
-    State(pool): State<Arc<PgPool>>,
+pub async fn draft<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    State(limits): State<Arc<QuoteLimits>>,
+    ctx: AuditContext,
     Json(req): Json<QuoteReq>,
-) -> Result<(StatusCode, Json<Quote>), StatusCode>
-{
-    match sqlx::query_as::<_, Quote>("INSERT INTO quotes (id, author, quote) VALUES ($1, $2, $3) RETURNING *")
-        .bind(Uuid::new_v4())
-        .bind(req.author)
-        .bind(req.quote)
-        .fetch_one(&*pool)
-        .await
-    {
+) -> Result<(StatusCode, Json<Quote>), Response>
+{
+    let req = req.trimmed();
+    validate_quote(&limits, &req.author, &req.quote, req.tags.as_deref())
+        .map_err(IntoResponse::into_response)?;
+
+    match store.create(&ctx, req.author, req.quote, req.tags.unwrap_or_default()).await {
         Ok(q) => Ok((StatusCode::CREATED, Json(q))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => Err(store_status(e).into_response()),
+    }
+}
+
+/// Attempts at merging a patch into a quote changing meanwhile
+const PATCH_ATTEMPTS: usize = 3;
+
+/// Fields set by a patch
+#[derive(Debug, Default)]
+struct QuotePatch {
+    author: Option<String>,
+    quote: Option<String>,
+    /// `Some(vec![])` removes every tag
+    tags: Option<Vec<String>>,
+}
+
+impl TryFrom<Value> for QuotePatch {
+    type Error = ValidationErrors;
+
+    fn try_from(patch: Value) -> Result<Self, Self::Error> {
+        let mut errors = ValidationErrors::default();
+        let Value::Object(patch) = patch else {
+            errors.add("body", "must be a JSON object");
+            return Err(errors);
+        };
+
+        let mut parsed = QuotePatch::default();
+        for (field, value) in patch {
+            match (field.as_str(), value) {
+                ("author", Value::String(s)) => { parsed.author = Some(s); },
+                ("quote", Value::String(s)) => { parsed.quote = Some(s); },
+                ("author" | "quote", Value::Null) => errors.add(&field, "cannot be removed"),
+                ("author" | "quote", _) => errors.add(&field, "must be a string"),
+                ("tags", Value::Null) => { parsed.tags = Some(Vec::new()); },
+                ("tags", Value::Array(values)) => {
+                    match values.into_iter().map(|v| match v { Value::String(s) => Some(s), _ => None }).collect() {
+                        Some(t) => { parsed.tags = Some(t); },
+                        None => errors.add("tags", "must be an array of strings"),
+                    };
+                },
+                ("tags", _) => errors.add("tags", "must be an array of strings"),
+                (_, _) => errors.add(&field, "cannot be patched"),
+            };
+        };
+        errors.check()?;
+
+        Ok(parsed)
+    }
+}
+
+/// Partially update a record with givin ID (JSON merge patch, RFC 7396)
+///
+/// `author` and `quote` can be replaced, `tags` replaced or removed with `null`.
+/// With `If-Match: "<version>"` the patch only applies to that version of the quote,
+/// otherwise it is merged into the current one.
+pub async fn patch<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    State(limits): State<Arc<QuoteLimits>>,
+    ctx: AuditContext,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Result<Json<Quote>, Response>
+{
+    let patch = QuotePatch::try_from(patch)
+        .map_err(IntoResponse::into_response)?;
+    let if_match = match headers.get(header::IF_MATCH) {
+        Some(v) => Some(v.to_str().ok()
+            .and_then(|v| v.trim().trim_matches('"').parse::<i32>().ok())
+            .ok_or_else(|| StatusCode::BAD_REQUEST.into_response())?),
+        None => None,
+    };
+
+    // merge into the version read, and start over if another update got in first
+    for _ in 0..PATCH_ATTEMPTS {
+        let current = store.get(id).await
+            .map_err(|e| store_status(e).into_response())?;
+        if if_match.is_some_and(|v| v != current.version) {
+            return Err(StatusCode::PRECONDITION_FAILED.into_response());
+        };
+
+        let author = patch.author.as_deref().unwrap_or(&current.author).trim().to_owned();
+        let quote = patch.quote.as_deref().unwrap_or(&current.quote).trim().to_owned();
+        validate_quote(&limits, &author, &quote, patch.tags.as_deref())
+            .map_err(IntoResponse::into_response)?;
+
+        match store.update(&ctx, id, Some(current.version), author, quote, patch.tags.clone()).await {
+            Ok(q) => return Ok(Json(q)),
+            Err(StoreError::Conflict) if if_match.is_none() => continue,
+            Err(e) => return Err(store_status(e).into_response()),
+        };
+    };
+
+    Err(StatusCode::CONFLICT.into_response())
+}
+
+const PAGE_SIZE: u32 = 10;
+const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    page: Option<u32>,
+    limit: Option<u32>,
+}
+
+/// Tag filter of a listing, e.g. `?tags=holiday,funny&match=all`
+#[derive(Debug, Deserialize)]
+pub struct TagParams {
+    tags: Option<String>,
+    #[serde(rename = "match", default)]
+    tag_match: TagMatch,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    /// Quotes with all of the tags
+    All,
+    /// Quotes with any of the tags
+    #[default]
+    Any,
+}
+
+impl From<TagParams> for TagFilter {
+    fn from(params: TagParams) -> Self {
+        let tags = params.tags
+            .map(|t| t.split(',').map(str::to_owned).collect())
+            .unwrap_or_default();
+        TagFilter::new(tags, matches!(params.tag_match, TagMatch::All))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListResp {
+    quotes: Vec<Quote>,
+    page: u32,
+    next_page: Option<u32>,
+}
+
+/// Validated `(page, limit)` of a listing
+fn page_of(params: &ListParams) -> Result<(u32, u32), StatusCode> {
+    let page = params.page.unwrap_or(1);
+    let limit = params.limit.unwrap_or(PAGE_SIZE);
+    if page == 0 || !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    Ok((page, limit))
+}
+
+/// Cut the extra quote fetched to detect a next page
+fn list_resp(mut quotes: Vec<Quote>, page: u32, limit: u32) -> ListResp {
+    let next_page = if quotes.len() > limit as usize {
+        quotes.truncate(limit as usize);
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    ListResp {
+        quotes,
+        page,
+        next_page,
+    }
+}
+
+/// List quotes, oldest first
+pub async fn list<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Query(params): Query<ListParams>,
+    Query(tags): Query<TagParams>,
+) -> Result<Json<ListResp>, StatusCode>
+{
+    let (page, limit) = page_of(&params)?;
+    let filter = QuoteFilter {
+        author: None,
+        tags: tags.into(),
+    };
+
+    // fetch one extra quote to know whether there is a next page
+    let quotes = store.list(u64::from(page - 1) * u64::from(limit), u64::from(limit) + 1, &filter).await
+        .map_err(store_status)?;
+
+    Ok(Json(list_resp(quotes, page, limit)))
+}
+
+/// List authors with their quote count and latest quote date
+pub async fn authors<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+) -> Result<Json<Vec<Author>>, StatusCode>
+{
+    store.authors().await
+        .map(Json)
+        .map_err(store_status)
+}
+
+/// List quotes of an author, oldest first
+pub async fn author_quotes<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<ListResp>, StatusCode>
+{
+    let (page, limit) = page_of(&params)?;
+
+    let quotes = store.author_quotes(id, u64::from(page - 1) * u64::from(limit), u64::from(limit) + 1).await
+        .map_err(store_status)?;
+
+    Ok(Json(list_resp(quotes, page, limit)))
+}
+
+/// List tags in use, with their quote count
+pub async fn tags<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+) -> Result<Json<Vec<Tag>>, StatusCode>
+{
+    store.tags().await
+        .map(Json)
+        .map_err(store_status)
+}
+
+/// Seed of the quote of the day, changing it reshuffles every day
+const DAILY_SEED: &[u8] = b"cch24 quote of the day";
+
+#[derive(Debug, Deserialize)]
+pub struct DailyParams {
+    date: Option<NaiveDate>,
+}
+
+/// Pick the quote at a uniformly distributed position, `pick` maps the number of candidates to one
+async fn pick_quote<S: QuoteStore>(
+    store: &S,
+    filter: &QuoteFilter,
+    pick: impl Fn(u64) -> u64,
+) -> Result<Quote, StatusCode>
+{
+    // the set may change between counting and fetching, so try a few times
+    for _ in 0..3 {
+        let count = store.count(filter).await.map_err(store_status)?;
+        if count == 0 {
+            return Err(StatusCode::NOT_FOUND);
+        };
+
+        if let Some(q) = store.list(pick(count), 1, filter).await.map_err(store_status)?.pop() {
+            return Ok(q);
+        };
+    };
+
+    Err(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// Quote of the day, stable for a given date as long as the quotes don't change
+pub async fn daily<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Query(params): Query<DailyParams>,
+) -> Result<Json<Quote>, StatusCode>
+{
+    let date = params.date.unwrap_or_else(|| Local::now().date_naive());
+    let digest = Sha256::new()
+        .chain_update(DAILY_SEED)
+        .chain_update(date.to_string())
+        .finalize();
+    let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+
+    pick_quote(&*store, &QuoteFilter::default(), |count| hash % count).await
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RandomParams {
+    author: Option<String>,
+}
+
+/// A random quote, optionally by an author and with some tags
+pub async fn random<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Query(params): Query<RandomParams>,
+    Query(tags): Query<TagParams>,
+) -> Result<Json<Quote>, StatusCode>
+{
+    let filter = QuoteFilter {
+        author: params.author,
+        tags: tags.into(),
+    };
+
+    pick_quote(&*store, &filter, |count| rand::thread_rng().gen_range(0..count)).await
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditParams {
+    quote_id: Option<Uuid>,
+    actor: Option<String>,
+    from: Option<DateTime<Local>>,
+    to: Option<DateTime<Local>>,
+    page: Option<u32>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditResp {
+    entries: Vec<AuditEntry>,
+    page: u32,
+    next_page: Option<u32>,
+}
+
+/// Whether `headers` carry the bearer token of the audit log, never if it has none
+fn audit_authorized(config: &Config, headers: &HeaderMap) -> bool {
+    let token = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    // compare digests so the comparison takes as long whatever the token
+    match token {
+        Some(t) if !config.audit.token.is_empty() => {
+            Sha256::digest(t.as_bytes()) == Sha256::digest(config.audit.token.as_bytes())
+        },
+        _ => false,
+    }
+}
+
+/// Audit log of quote mutations, newest first, e.g. `?actor=santa&from=2024-12-24T00:00:00Z`
+///
+/// Requires `Authorization: Bearer <audit.token>`.
+pub async fn audit<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    State(config): State<Arc<Config>>,
+    headers: HeaderMap,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<AuditResp>, Response>
+{
+    if !audit_authorized(&config, &headers) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        ).into_response());
+    };
+
+    let (page, limit) = page_of(&ListParams { page: params.page, limit: params.limit })
+        .map_err(IntoResponse::into_response)?;
+    let filter = AuditFilter {
+        quote_id: params.quote_id,
+        actor: params.actor,
+        from: params.from,
+        to: params.to,
+    };
+
+    let mut entries = store.audit_log(&filter, u64::from(page - 1) * u64::from(limit), u64::from(limit) + 1).await
+        .map_err(|e| store_status(e).into_response())?;
+    let next_page = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    Ok(Json(AuditResp {
+        entries,
+        page,
+        next_page,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    q: String,
+    author: Option<String>,
+    page: Option<u32>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResp {
+    hits: Vec<QuoteHit>,
+    page: u32,
+    next_page: Option<u32>,
+}
+
+/// Full-text search over quotes, ranked by relevance
+///
+/// `snippet` is an HTML fragment: the quote text escaped, with matches wrapped in `<mark>`.
+pub async fn search<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Query(params): Query<SearchParams>,
+    Query(tags): Query<TagParams>,
+) -> Result<Json<SearchResp>, StatusCode>
+{
+    let page = params.page.unwrap_or(1);
+    let limit = params.limit.unwrap_or(PAGE_SIZE);
+    if page == 0 || !(1..=MAX_PAGE_SIZE).contains(&limit) || params.q.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    let query = SearchQuery {
+        text: params.q,
+        author: params.author,
+        tags: tags.into(),
+    };
+
+    // fetch one extra hit to know whether there is a next page
+    let mut hits = store.search(&query, u64::from(page - 1) * u64::from(limit), u64::from(limit) + 1).await
+        .map_err(store_status)?;
+
+    let next_page = if hits.len() > limit as usize {
+        hits.truncate(limit as usize);
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    for hit in &mut hits {
+        hit.snippet = highlight_html(&hit.snippet);
+    };
+
+    Ok(Json(SearchResp {
+        hits,
+        page,
+        next_page,
+    }))
+}
+
+/// Errors reported by an import, later ones are only counted
+const MAX_IMPORT_ERRORS: usize = 100;
+
+/// Interchange format of quote import and export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+impl QuoteFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            QuoteFormat::Jsonl => "application/x-ndjson",
+            QuoteFormat::Csv => "text/csv",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    format: Option<QuoteFormat>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// A quote as it appears in an import, only `author` and `quote` are required
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    id: Option<Uuid>,
+    author: String,
+    quote: String,
+    created_at: Option<DateTime<Local>>,
+    version: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportError {
+    line: u64,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResp {
+    dry_run: bool,
+    /// Rows inserted, or that would have been inserted in a dry run
+    imported: u64,
+    /// Rows whose `id` already exists
+    skipped: u64,
+    /// First `MAX_IMPORT_ERRORS` errors
+    errors: Vec<ImportError>,
+    /// Number of invalid rows, including those past `errors`
+    error_count: u64,
+}
+
+impl ImportRow {
+    /// Trimmed and checked like a quote created through the API
+    fn validate(self, limits: &QuoteLimits) -> Result<ImportedQuote, String> {
+        let author = self.author.trim().to_owned();
+        let quote = self.quote.trim().to_owned();
+        validate_quote(limits, &author, &quote, None)
+            .map_err(|e| e.to_string())?;
+        let version = self.version.unwrap_or(1);
+        if version < 1 {
+            return Err("`version` must be positive".to_owned());
+        };
+
+        Ok(ImportedQuote {
+            id: self.id.unwrap_or_else(Uuid::new_v4),
+            author,
+            quote,
+            created_at: self.created_at.unwrap_or_else(Local::now),
+            version,
+        })
+    }
+}
+
+/// Bulk import quotes from JSON Lines or CSV, at most `quotes.import_max_bytes` of them
+///
+/// Rows go to the store as they are parsed, nothing is imported unless every line is valid
+/// and this is not a dry run.
+pub async fn import<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    State(limits): State<Arc<QuoteLimits>>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<ImportParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ImportResp>, StatusCode>
+{
+    let format = params.format.unwrap_or_else(|| {
+        match headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+            Some(t) if t.starts_with("text/csv") => QuoteFormat::Csv,
+            _ => QuoteFormat::Jsonl,
+        }
+    });
+
+    // the body is read by hand, so `DefaultBodyLimit` does not apply
+    let too_large = Arc::new(AtomicBool::new(false));
+    let body = Body::new(Limited::new(body, config.quotes.import_max_bytes));
+    let reader = StreamReader::new(body.into_data_stream().map_err({
+        let too_large = too_large.clone();
+        move |e| {
+            let e = e.into_inner();
+            if e.is::<LengthLimitError>() {
+                too_large.store(true, Ordering::Relaxed);
+            };
+            io::Error::other(e)
+        }
+    }));
+
+    let mut rows = std::pin::pin!(parse_rows(format, reader));
+    let mut tally = ImportTally::default();
+
+    let imported = {
+        let (rows, tally, limits) = (&mut rows, &mut tally, &limits);
+        let quotes = async_stream::stream! {
+            while let Some((line, row)) = rows.next().await {
+                match row.and_then(|row| row.validate(limits)) {
+                    Ok(q) => {
+                        tally.rows += 1;
+                        yield Ok(q);
+                    },
+                    Err(error) => {
+                        tally.error(line, error);
+                        yield Err(StoreError::Aborted);
+                    },
+                };
+            };
+        };
+        store.import(quotes, params.dry_run).await
+    };
+
+    let inserted = match imported {
+        Ok(inserted) => inserted,
+        // the import is doomed, keep validating for the report
+        Err(StoreError::Aborted) => {
+            while let Some((line, row)) = rows.next().await {
+                if let Err(error) = row.and_then(|row| row.validate(&limits)) {
+                    tally.error(line, error);
+                };
+            };
+            0
+        },
+        Err(e) => return Err(store_status(e)),
+    };
+    if too_large.load(Ordering::Relaxed) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    };
+
+    Ok(Json(ImportResp {
+        dry_run: params.dry_run,
+        imported: inserted,
+        skipped: if tally.error_count == 0 { tally.rows - inserted } else { 0 },
+        errors: tally.errors,
+        error_count: tally.error_count,
+    }))
+}
+
+/// Valid rows and errors of an import so far
+#[derive(Default)]
+struct ImportTally {
+    rows: u64,
+    errors: Vec<ImportError>,
+    error_count: u64,
+}
+
+impl ImportTally {
+    fn error(&mut self, line: u64, error: String) {
+        self.error_count += 1;
+        if self.errors.len() < MAX_IMPORT_ERRORS {
+            self.errors.push(ImportError { line, error });
+        };
+    }
+}
+
+/// Parse an upload into rows tagged with their (1-based) line number
+fn parse_rows<R>(
+    format: QuoteFormat,
+    reader: R,
+) -> impl Stream<Item = (u64, Result<ImportRow, String>)>
+where
+    R: tokio::io::AsyncBufRead + Unpin + Send + 'static,
+{
+    async_stream::stream! {
+        match format {
+            QuoteFormat::Jsonl => {
+                let mut lines = reader.lines();
+                let mut line = 0;
+                loop {
+                    line += 1;
+                    match lines.next_line().await {
+                        Ok(Some(l)) if l.trim().is_empty() => continue,
+                        Ok(Some(l)) => yield (line, serde_json::from_str::<ImportRow>(&l).map_err(|e| e.to_string())),
+                        Ok(None) => break,
+                        Err(e) => { yield (line, Err(e.to_string())); break; },
+                    };
+                };
+            },
+            QuoteFormat::Csv => {
+                let mut reader = csv_async::AsyncReaderBuilder::new()
+                    .create_reader(reader);
+                let headers = match reader.headers().await {
+                    Ok(h) => h.clone(),
+                    Err(e) => { yield (1, Err(e.to_string())); return; },
+                };
+                let mut records = reader.records();
+                while let Some(record) = records.next().await {
+                    match record {
+                        Ok(r) => {
+                            let line = r.position().map_or(0, |p| p.line());
+                            yield (line, r.deserialize::<ImportRow>(Some(&headers)).map_err(|e| e.to_string()));
+                        },
+                        Err(e) => {
+                            let line = e.position().map_or(0, |p| p.line());
+                            yield (line, Err(e.to_string()));
+                        },
+                    };
+                };
+            },
+        };
+    }
+}
+
+/// A quote as it appears in a CSV export, one field per header column
+#[derive(Debug, Serialize)]
+struct ExportRow<'a> {
+    id: Uuid,
+    author: &'a str,
+    quote: &'a str,
+    created_at: DateTime<Local>,
+    version: i32,
+}
+
+impl<'a> From<&'a Quote> for ExportRow<'a> {
+    fn from(q: &'a Quote) -> Self {
+        Self {
+            id: q.id,
+            author: &q.author,
+            quote: &q.quote,
+            created_at: q.created_at,
+            version: q.version,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    format: Option<QuoteFormat>,
+}
+
+/// Stream every quote as JSON Lines or CSV
+pub async fn export<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    Query(params): Query<ExportParams>,
+) -> Response
+{
+    let format = params.format.unwrap_or_default();
+
+    (
+        [(header::CONTENT_TYPE, format.content_type())],
+        Body::from_stream(export_stream(store.export(), format)),
+    ).into_response()
+}
+
+fn export_stream(
+    quotes: impl Stream<Item = Result<Quote, StoreError>> + Send + 'static,
+    format: QuoteFormat,
+) -> impl Stream<Item = Result<Bytes, Box<dyn Error + Send + Sync>>>
+{
+    async_stream::try_stream! {
+        if format == QuoteFormat::Csv {
+            yield Bytes::from_static(b"id,author,quote,created_at,version\n");
+        };
+
+        let mut quotes = std::pin::pin!(quotes);
+        while let Some(q) = quotes.try_next().await? {
+            yield encode_quote(format, &q)?;
+        };
+    }
+}
+
+fn encode_quote(format: QuoteFormat, q: &Quote) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+    match format {
+        QuoteFormat::Jsonl => {
+            let mut line = serde_json::to_vec(q)?;
+            line.push(b'\n');
+            Ok(line.into())
+        },
+        QuoteFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            writer.serialize(ExportRow::from(q))?;
+            Ok(writer.into_inner()?.into())
+        },
+    }
+}
+
+/// How long a gap in the sequence of changes may be filled by a transaction committing late,
+/// past that it is taken for a rolled back one
+const CHANGE_SETTLE: Duration = Duration::from_secs(5);
+
+/// Changes delivered to one subscriber, which may commit out of `seq` order
+struct Delivered {
+    /// Every change up to here is delivered, or given up on
+    floor: i64,
+    /// Changes delivered above `floor`, with when they were
+    above: BTreeMap<i64, Instant>,
+}
+
+impl Delivered {
+    fn new(floor: i64) -> Self {
+        Self {
+            floor,
+            above: BTreeMap::new(),
+        }
+    }
+
+    fn is_new(&self, seq: i64) -> bool {
+        seq > self.floor && !self.above.contains_key(&seq)
+    }
+
+    fn insert(&mut self, seq: i64) {
+        self.above.insert(seq, Instant::now());
+
+        // raise the floor over contiguous changes and settled gaps
+        while let Some((&seq, &at)) = self.above.first_key_value() {
+            if seq != self.floor + 1 && at.elapsed() < CHANGE_SETTLE {
+                break;
+            };
+            self.floor = seq;
+            self.above.pop_first();
+        };
+    }
+}
+
+/// Event of a change, its ID is where to resume from so that late changes are not lost
+fn change_event(change: &QuoteChange, resume_from: i64) -> Event {
+    Event::default()
+        .event("change")
+        .id(resume_from.to_string())
+        .json_data(change)
+        .unwrap()
+}
+
+/// Server-sent events for every quote change, resumable with `Last-Event-ID`
+///
+/// Changes commit out of `seq` order, so event IDs trail behind the `seq` of the latest
+/// change and resuming may repeat a few changes, tell them apart by `seq`.
+pub async fn changes<S: QuoteStore>(
+    State(store): State<Arc<S>>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode>
+{
+    let resume_from = headers.get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.parse::<i64>().ok());
+    // a new client starts from now, with a floor to catch up from if it lags
+    let floor = match resume_from {
+        Some(seq) => seq,
+        None => store.last_change_seq().await.map_err(store_status)?,
+    };
+    let mut delivered = Delivered::new(floor);
+    // subscribe before reading the backlog so nothing falls in between
+    let mut rx = store.subscribe();
+
+    let stream = async_stream::stream! {
+        let mut catch_up = true;
+        loop {
+            if catch_up {
+                let backlog = match store.changes_since(delivered.floor).await {
+                    Ok(backlog) => backlog,
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to read quote change backlog");
+                        break;
+                    },
+                };
+                for change in backlog {
+                    if delivered.is_new(change.seq) {
+                        delivered.insert(change.seq);
+                        yield Ok(change_event(&change, delivered.floor));
+                    };
+                };
+                catch_up = false;
+            };
+
+            match rx.recv().await {
+                // skip what the backlog already delivered
+                Ok(change) => if delivered.is_new(change.seq) {
+                    delivered.insert(change.seq);
+                    yield Ok(change_event(&change, delivered.floor));
+                },
+                // missed changes, read them back from the store
+                Err(broadcast::error::RecvError::Lagged(_)) => { catch_up = true; },
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+        };
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::store::MemoryQuoteStore;
+    use super::*;
+
+    async fn patched(store: &Arc<MemoryQuoteStore>, id: Uuid, if_match: Option<&str>, body: Value) -> Result<Quote, StatusCode> {
+        let mut headers = HeaderMap::new();
+        if let Some(v) = if_match {
+            headers.insert(header::IF_MATCH, v.parse().unwrap());
+        };
+        patch(State(store.clone()), State(Arc::new(QuoteLimits::default())), AuditContext::default(), Path(id), headers, Json(body)).await
+            .map(|Json(q)| q)
+            .map_err(|r| r.status())
+    }
+
+    #[tokio::test]
+    async fn patch_merges_trimmed_fields() {
+        let store = Arc::new(MemoryQuoteStore::new());
+        let q = store.create(&AuditContext::default(), "Santa".to_owned(), "Ho".to_owned(), vec!["jolly".to_owned()]).await.unwrap();
+
+        let q = patched(&store, q.id, None, json!({ "quote": "  Ho ho ho \n" })).await.unwrap();
+        assert_eq!((q.author.as_str(), q.quote.as_str(), q.version), ("Santa", "Ho ho ho", 2));
+        assert_eq!(q.tags, ["jolly"]);
+
+        let q = patched(&store, q.id, Some("\"2\""), json!({ "author": " Mrs Claus ", "tags": null })).await.unwrap();
+        assert_eq!((q.author.as_str(), q.quote.as_str(), q.version), ("Mrs Claus", "Ho ho ho", 3));
+        assert!(q.tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_snippets_are_html() {
+        let store = Arc::new(MemoryQuoteStore::new());
+        store.create(&AuditContext::default(), "Santa".to_owned(), "<b>AT&T</b> sleigh".to_owned(), Vec::new()).await.unwrap();
+
+        let params = SearchParams { q: "sleigh".to_owned(), author: None, page: None, limit: None };
+        let tags = TagParams { tags: None, tag_match: TagMatch::default() };
+        let Json(resp) = search(State(store), Query(params), Query(tags)).await.unwrap();
+        assert_eq!(resp.hits[0].snippet, "&lt;b&gt;AT&amp;T&lt;/b&gt; <mark>sleigh</mark>");
+    }
+
+    #[tokio::test]
+    async fn patch_checks_if_match() {
+        let store = Arc::new(MemoryQuoteStore::new());
+        let q = store.create(&AuditContext::default(), "Santa".to_owned(), "Ho".to_owned(), Vec::new()).await.unwrap();
+
+        assert_eq!(patched(&store, q.id, Some("\"2\""), json!({ "quote": "Stale" })).await.unwrap_err(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(patched(&store, q.id, Some("W/nope"), json!({ "quote": "Stale" })).await.unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(patched(&store, q.id, None, json!({ "quote": "   " })).await.unwrap_err(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(store.get(q.id).await.unwrap().quote, "Ho");
+
+        assert_eq!(patched(&store, q.id, Some("1"), json!({ "quote": "Fresh" })).await.unwrap().version, 2);
+    }
+
+    #[test]
+    fn forwarded_for_only_from_trusted_proxies() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let proxy = ip("10.0.0.1");
+        let trusted = [proxy, ip("10.0.0.2")];
+
+        assert_eq!(client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &trusted), Some(ip("1.2.3.4")));
+        assert_eq!(client_ip(Some(proxy), None, &trusted), Some(proxy));
+        assert_eq!(client_ip(Some(proxy), Some("5.6.7.8"), &trusted), Some(ip("5.6.7.8")));
+        // the client may send its own X-Forwarded-For, only what trusted proxies added counts
+        assert_eq!(client_ip(Some(proxy), Some("6.6.6.6, 5.6.7.8, 10.0.0.2"), &trusted), Some(ip("5.6.7.8")));
+        assert_eq!(client_ip(Some(proxy), Some("not an address"), &trusted), Some(proxy));
+        assert_eq!(client_ip(None, Some("5.6.7.8"), &trusted), None);
+    }
+
+    #[test]
+    fn audit_needs_the_token() {
+        let headers = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            headers
+        };
+        let mut config = Config::default();
+
+        assert!(!audit_authorized(&config, &headers("")));
+        config.audit.token = "sesame".to_owned();
+        assert!(audit_authorized(&config, &headers("sesame")));
+        assert!(!audit_authorized(&config, &headers("open")));
+        assert!(!audit_authorized(&config, &HeaderMap::new()));
+    }
+
+    #[test]
+    fn late_changes_are_delivered() {
+        let mut d = Delivered::new(1);
+        assert!(!d.is_new(1));
+
+        d.insert(3);
+        assert_eq!(d.floor, 1);
+        // 2 commits after 3
+        assert!(d.is_new(2));
+        d.insert(2);
+        assert_eq!(d.floor, 3);
+        assert!(!d.is_new(2) && !d.is_new(3));
+    }
+
+    #[test]
+    fn settled_gaps_are_given_up() {
+        let mut d = Delivered::new(0);
+        d.above.insert(2, Instant::now() - CHANGE_SETTLE);
+        d.insert(4);
+        // 1 never came, 3 may still come
+        assert_eq!(d.floor, 2);
+        assert!(d.is_new(3) && !d.is_new(4));
+    }
+
+    async fn imported(store: &Arc<MemoryQuoteStore>, limits: QuoteLimits, jsonl: &str) -> ImportResp {
+        let params = ImportParams { format: Some(QuoteFormat::Jsonl), dry_run: false };
+        let Json(resp) = import(State(store.clone()), State(Arc::new(limits)), State(Arc::new(Config::default())), Query(params), HeaderMap::new(), Body::from(jsonl.to_owned())).await.unwrap();
+        resp
+    }
+
+    #[tokio::test]
+    async fn import_validates_like_draft() {
+        let store = Arc::new(MemoryQuoteStore::new());
+        let limits = QuoteLimits { max_author_len: 5, ..QuoteLimits::default() };
+
+        let rows = [
+            r#"{"author": "Santa", "quote": "fine"}"#,
+            r#"{"author": "   ", "quote": "no author"}"#,
+            r#"{"author": "Rudolph", "quote": "too long an author"}"#,
+            r#"{"author": "Elf", "quote": "bell\u0007"}"#,
+        ];
+        let resp = imported(&store, limits.clone(), &rows.join("\n")).await;
+        assert_eq!((resp.imported, resp.error_count), (0, 3));
+        assert_eq!(resp.errors.iter().map(|e| e.line).collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(resp.errors[1].error, "`author` must be at most 5 characters");
+        assert_eq!(resp.errors[2].error, "`quote` must not contain control characters");
+
+        let resp = imported(&store, limits, r#"{"author": " Santa ", "quote": "\n Ho ho ho \n"}"#).await;
+        assert_eq!(resp.imported, 1);
+        let q = &store.list(0, 1, &QuoteFilter::default()).await.unwrap()[0];
+        assert_eq!((q.author.as_str(), q.quote.as_str()), ("Santa", "Ho ho ho"));
+    }
+
+    #[tokio::test]
+    async fn import_is_size_limited() {
+        let store = Arc::new(MemoryQuoteStore::new());
+        let mut config = Config::default();
+        config.quotes.import_max_bytes = 200;
+        let rows = [r#"{"author": "Santa", "quote": "Ho ho ho"}"#; 10].join("\n");
+
+        for dry_run in [true, false] {
+            let params = ImportParams { format: Some(QuoteFormat::Jsonl), dry_run };
+            let resp = import(State(store.clone()), State(Arc::new(QuoteLimits::default())), State(Arc::new(config.clone())), Query(params), HeaderMap::new(), Body::from(rows.clone())).await;
+            assert_eq!(resp.unwrap_err(), StatusCode::PAYLOAD_TOO_LARGE);
+        };
+        assert_eq!(store.count(&QuoteFilter::default()).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn lagging_new_clients_catch_up() {
+        use http_body_util::BodyExt;
+
+        let store = Arc::new(MemoryQuoteStore::new());
+        let ctx = AuditContext::default();
+        store.create(&ctx, "Santa".to_owned(), "Before".to_owned(), Vec::new()).await.unwrap();
+
+        let sse = changes(State(store.clone()), HeaderMap::new()).await.unwrap();
+        // more changes than the feed holds before the client reads any
+        for i in 0..300 {
+            store.create(&ctx, "Santa".to_owned(), format!("Ho {}", i), Vec::new()).await.unwrap();
+        };
+
+        let mut body = sse.into_response().into_body();
+        let mut seqs = Vec::new();
+        while seqs.len() < 300 {
+            let frame = tokio::time::timeout(Duration::from_secs(5), body.frame()).await
+                .expect("changes not delivered")
+                .unwrap()
+                .unwrap();
+            let Ok(data) = frame.into_data() else { continue };
+            for line in std::str::from_utf8(&data).unwrap().lines() {
+                if let Some(json) = line.strip_prefix("data: ") {
+                    seqs.push(serde_json::from_str::<QuoteChange>(json).unwrap().seq);
+                };
+            };
+        };
+        // from now on, nothing lost to the lag
+        assert_eq!(seqs, (2..=301).collect::<Vec<i64>>());
     }
 }
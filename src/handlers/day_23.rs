@@ -1,88 +1,702 @@
-use std::str::FromStr;
-
-use axum::{extract::{Path, Multipart}, http::StatusCode, response::Html};
-
-pub async fn star() -> Html<&'static str> {
-    Html(r#"<html><div id="star" class="lit"></div></html>"#)
-}
-
-pub async fn color(Path(color): Path<String>) -> Result<Html<String>, StatusCode> {
-    let color = html_escape::encode_safe(&color);
-
-    Ok(Html(format!(r#"
-        <html>
-            <div class="present {}" hx-get="/23/present/{}" hx-swap="outerHTML">
-                <div class="ribbon"></div>
-                <div class="ribbon"></div>
-                <div class="ribbon"></div>
-                <div class="ribbon"></div>
-            </div>
-        </html>
-    "#, color, match color.as_ref() {
-        "red" => "blue",
-        "blue" => "purple",
-        "purple" => "red",
-        _ => { return Err(StatusCode::IM_A_TEAPOT); },
-    })))
+use std::{collections::BTreeMap, convert::Infallible, io::Read, str::FromStr, sync::{Arc, Mutex}};
+
+use askama::Template;
+use axum::{
+    extract::{multipart::{Field, MultipartError}, Json, Path, Multipart, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+};
+use futures::Stream;
+use cargo_lock::Lockfile;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{
+    lockfile::{AdvisoryDb, Audit, Change, Diff, PackageVersion, Report, TreeNode, VersionChange},
+    templates::{render, HtmlTemplate},
+    themes::{Theme, Themes},
+};
+
+#[derive(Template)]
+#[template(path = "day_23/star.html")]
+struct StarTemplate;
+
+pub async fn star() -> HtmlTemplate<impl Template> {
+    HtmlTemplate(StarTemplate)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThemeParams {
+    theme: Option<String>,
+}
+
+/// Theme named by the `theme` query parameter, else the `theme` cookie, else the default one
+fn select_theme<'a>(themes: &'a Themes, params: &ThemeParams, headers: &HeaderMap) -> Result<&'a Theme, StatusCode> {
+    let cookie = headers.get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|c| c.split(';').find_map(|kv| kv.trim().strip_prefix("theme=")));
+
+    match params.theme.as_deref().or(cookie) {
+        Some(name) => themes.get(name).ok_or(StatusCode::BAD_REQUEST),
+        None => Ok(themes.default_theme()),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "day_23/present.html")]
+struct PresentTemplate {
+    color: String,
+    next: String,
+    theme: Option<String>,
+}
+
+pub async fn color(
+    State(themes): State<Arc<Themes>>,
+    Path(color): Path<String>,
+    Query(params): Query<ThemeParams>,
+    headers: HeaderMap,
+) -> Result<HtmlTemplate<impl Template>, StatusCode>
+{
+    let theme = select_theme(&themes, &params, &headers)?;
+    let next = theme.next(&color)
+        .ok_or(StatusCode::IM_A_TEAPOT)?
+        .name
+        .clone();
+
+    Ok(HtmlTemplate(PresentTemplate {
+        color,
+        next,
+        // a theme from the query has to be passed on, a cookie is sent again anyway
+        theme: params.theme,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThemeResp {
+    #[serde(flatten)]
+    theme: Theme,
+    default: bool,
+    css: String,
+}
+
+/// Every theme with its palette and CSS
+pub async fn themes(
+    State(themes): State<Arc<Themes>>,
+) -> Json<Vec<ThemeResp>>
+{
+    let default = &themes.default_theme().name;
+
+    Json(themes.all().iter()
+        .map(|t| ThemeResp {
+            theme: t.clone(),
+            default: t.name == *default,
+            css: t.css(),
+        })
+        .collect())
+}
+
+/// Stylesheet of a theme, for `<link rel="stylesheet">`
+pub async fn theme_css(
+    State(themes): State<Arc<Themes>>,
+    Path(name): Path<String>,
+) -> Result<Response, StatusCode>
+{
+    let theme = themes.get(&name)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "text/css")], theme.css()).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "day_23/ornament_echo.html")]
+struct OrnamentEchoTemplate {
+    n: String,
+    on: bool,
+    next_state: &'static str,
 }
 
 pub async fn ornament(
     Path((state, n)): Path<(String, String)>,
-) -> Result<Html<String>, StatusCode>
+) -> Result<HtmlTemplate<impl Template>, StatusCode>
 {
-    let state = html_escape::encode_safe(&state);
-    let n = html_escape::encode_safe(&n);
-
-    let (curr_state, next_state) = match state.as_ref() {
-        "on" => (" on", "off"),
-        "off" => ("", "on"),
+    let (on, next_state) = match state.as_str() {
+        "on" => (true, "off"),
+        "off" => (false, "on"),
         _ => { return Err(StatusCode::IM_A_TEAPOT); },
     };
 
-    Ok(Html(format!(r#"
-        <html>
-            <div class="ornament{}"
-                id="ornament{}"
-                hx-trigger="load delay:2s once"
-                hx-get="/23/ornament/{}/{}"
-                hx-swap="outerHTML">
-            </div>
-        </html>
-        "#, curr_state, n, next_state, n)
-    ))
-}
-
-pub async fn lockfile(mut multipart: Multipart) -> Result<Html<String>, StatusCode> {
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        if field.name() == Some("lockfile") {
-            // parse lockfile
-            let data = field.bytes().await.unwrap();
-            let lockfile = String::from_utf8(data.to_vec()).ok()
-                .and_then(|s| cargo_lock::Lockfile::from_str(s.as_str()).ok())
-                .ok_or(StatusCode::BAD_REQUEST)?;
-            
-            // handle packages
-            let mut resp = Vec::new();
-
-            for p in lockfile.packages {
-                if let Some(checksum) = p.checksum {
-                    let checksum = checksum.as_sha256()
-                        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
-        
-                    let color = format!("#{:x}{:x}{:x}", checksum[0], checksum[1], checksum[2]);
-                    let top = checksum[3];
-                    let left = checksum[4];
-        
-                    resp.push(format!(
-                        r#"<div style="background-color:{color};top:{top}px;left:{left}px;"></div>"#,
-                    ));
+    Ok(HtmlTemplate(OrnamentEchoTemplate { n, on, next_state }))
+}
+
+/// Size limit of lockfile uploads, both on the wire and once decompressed
+#[derive(Debug, Clone, Copy)]
+pub struct UploadLimit {
+    pub max_bytes: usize,
+}
+
+/// Room for the multipart boundaries, headers and ignored fields of an upload
+const MULTIPART_FRAMING: usize = 64 * 1024;
+
+/// Most lockfiles drawn by a single `/23/lockfile` upload
+pub const MAX_CANVASES: usize = 8;
+
+impl UploadLimit {
+    /// Limit of a whole request body carrying up to `fields` lockfiles
+    pub fn body_limit(&self, fields: usize) -> usize {
+        self.max_bytes * fields + MULTIPART_FRAMING
+    }
+}
+
+/// Status and message of a rejected upload
+type UploadError = (StatusCode, String);
+
+fn too_large(limit: UploadLimit) -> UploadError {
+    (StatusCode::PAYLOAD_TOO_LARGE, format!("lockfile is larger than {} bytes", limit.max_bytes))
+}
+
+fn multipart_error(e: MultipartError, limit: UploadLimit) -> UploadError {
+    match e.status() {
+        // the body limit surfaces here
+        StatusCode::PAYLOAD_TOO_LARGE => too_large(limit),
+        status => (status, e.body_text()),
+    }
+}
+
+/// Read a field chunk by chunk, failing as soon as it grows past the limit
+async fn read_field(mut field: Field<'_>, limit: UploadLimit) -> Result<Vec<u8>, UploadError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|e| multipart_error(e, limit))? {
+        if data.len() + chunk.len() > limit.max_bytes {
+            return Err(too_large(limit));
+        };
+        data.extend_from_slice(&chunk);
+    };
+    Ok(data)
+}
+
+/// Parse a `Cargo.lock`, gunzipping it first if it starts with the gzip magic bytes
+fn parse_lockfile(name: &str, data: Vec<u8>, limit: UploadLimit) -> Result<Lockfile, UploadError> {
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, format!("{}: {}", name, message));
+
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut inflated = Vec::new();
+        // one byte over the limit is enough to know it is too large
+        GzDecoder::new(data.as_slice())
+            .take(limit.max_bytes as u64 + 1)
+            .read_to_end(&mut inflated)
+            .map_err(|e| bad_request(format!("invalid gzip data: {}", e)))?;
+        if inflated.len() > limit.max_bytes {
+            return Err(too_large(limit));
+        };
+        inflated
+    } else {
+        data
+    };
+
+    let text = String::from_utf8(data)
+        .map_err(|_| bad_request("lockfile is not UTF-8".to_owned()))?;
+    Lockfile::from_str(&text)
+        .map_err(|e| bad_request(e.to_string()))
+}
+
+/// Parse the multipart fields called one of `names` as `Cargo.lock`s, in upload order,
+/// rejecting more than `max_fields` of them
+async fn read_lockfiles(
+    multipart: &mut Multipart,
+    names: &[&str],
+    max_fields: usize,
+    limit: UploadLimit,
+) -> Result<Vec<(String, Lockfile)>, UploadError>
+{
+    let mut lockfiles = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(|e| multipart_error(e, limit))? {
+        let Some(name) = field.name().filter(|n| names.contains(n)).map(str::to_owned) else {
+            continue;
+        };
+        if lockfiles.len() == max_fields {
+            return Err((StatusCode::BAD_REQUEST, format!("too many lockfiles, the limit is {}", max_fields)));
+        };
+        let data = read_field(field, limit).await?;
+        let lockfile = parse_lockfile(&name, data, limit)?;
+        lockfiles.push((name, lockfile));
+    };
+    Ok(lockfiles)
+}
+
+/// Take the first lockfile uploaded as `name`
+fn take_lockfile(lockfiles: &mut Vec<(String, Lockfile)>, name: &str) -> Result<Lockfile, UploadError> {
+    let i = lockfiles.iter().position(|(n, _)| n == name)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("missing {} field", name)))?;
+    Ok(lockfiles.remove(i).1)
+}
+
+/// Highest ornament ID kept on the server
+const MAX_ORNAMENT: u32 = 64;
+
+/// Authoritative on/off state of the ornaments, shared by every page
+pub struct Ornaments {
+    states: Mutex<BTreeMap<u32, bool>>,
+    changes: broadcast::Sender<(u32, bool)>,
+}
+
+impl Ornaments {
+    pub fn new() -> Arc<Self> {
+        let (changes, _) = broadcast::channel(64);
+        Arc::new(Self {
+            states: Mutex::new(BTreeMap::new()),
+            changes,
+        })
+    }
+
+    /// Ornaments start off
+    fn get(&self, n: u32) -> bool {
+        self.states.lock().unwrap().get(&n).copied().unwrap_or(false)
+    }
+
+    fn toggle(&self, n: u32) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let on = states.entry(n).or_default();
+        *on = !*on;
+        // nobody listening is fine
+        let _ = self.changes.send((n, *on));
+        *on
+    }
+
+    fn all(&self) -> Vec<(u32, bool)> {
+        self.states.lock().unwrap().iter().map(|(n, on)| (*n, *on)).collect()
+    }
+}
+
+fn ornament_id(n: u32) -> Result<u32, StatusCode> {
+    if (1..=MAX_ORNAMENT).contains(&n) { Ok(n) } else { Err(StatusCode::NOT_FOUND) }
+}
+
+/// An ornament that toggles on click and follows the `ornament{n}` events of `/23/ornament/events`
+#[derive(Template)]
+#[template(path = "day_23/ornament.html")]
+struct OrnamentTemplate {
+    n: u32,
+    on: bool,
+}
+
+/// Current state of an ornament
+pub async fn ornament_state(
+    State(ornaments): State<Arc<Ornaments>>,
+    Path(n): Path<u32>,
+) -> Result<HtmlTemplate<impl Template>, StatusCode>
+{
+    let n = ornament_id(n)?;
+    Ok(HtmlTemplate(OrnamentTemplate { n, on: ornaments.get(n) }))
+}
+
+/// Switch an ornament on or off for everyone
+pub async fn toggle_ornament(
+    State(ornaments): State<Arc<Ornaments>>,
+    Path(n): Path<u32>,
+) -> Result<HtmlTemplate<impl Template>, StatusCode>
+{
+    let n = ornament_id(n)?;
+    Ok(HtmlTemplate(OrnamentTemplate { n, on: ornaments.toggle(n) }))
+}
+
+fn ornament_event(n: u32, on: bool) -> Event {
+    Event::default()
+        .event(format!("ornament{}", n))
+        .data(render(&OrnamentTemplate { n, on }).unwrap_or_default())
+}
+
+/// Stream of re-rendered ornaments for the htmx SSE extension,
+/// starting with every ornament switched on so far
+pub async fn ornament_events(
+    State(ornaments): State<Arc<Ornaments>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>>
+{
+    // subscribe before the snapshot so nothing falls in between
+    let mut rx = ornaments.changes.subscribe();
+
+    let stream = async_stream::stream! {
+        let mut snapshot = true;
+        loop {
+            if snapshot {
+                for (n, on) in ornaments.all() {
+                    yield Ok(ornament_event(n, on));
                 };
+                snapshot = false;
             };
 
-            println!("{:?}", resp);
-            return Ok(Html(resp.join("\n")));
+            match rx.recv().await {
+                Ok((n, on)) => yield Ok(ornament_event(n, on)),
+                // missed changes, resend everything
+                Err(broadcast::error::RecvError::Lagged(_)) => { snapshot = true; },
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
         };
     };
 
-    Err(StatusCode::BAD_REQUEST)
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// One `.canvas` per lockfile
+#[derive(Template)]
+#[template(path = "day_23/canvas.html")]
+struct CanvasTemplate<'a> {
+    canvases: &'a [Vec<Ornament>],
+}
+
+/// Same layout as `#lockfilecanvas` in `assets/23.html`: 20px ornaments offset by up to 255px,
+/// one canvas per lockfile side by side
+#[derive(Template)]
+#[template(path = "day_23/canvas.svg")]
+struct CanvasSvgTemplate<'a> {
+    width: usize,
+    size: usize,
+    canvases: &'a [Vec<Ornament>],
+}
+
+/// Side of the lockfile canvas in pixels
+const CANVAS_SIZE: usize = 276;
+
+/// An ornament on the lockfile canvas, placed and coloured by the package checksum
+struct Ornament {
+    color: String,
+    top: u8,
+    left: u8,
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanvasFormat {
+    #[default]
+    Html,
+    Svg,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CanvasParams {
+    format: Option<CanvasFormat>,
+}
+
+/// Draw the packages of every `lockfile` field as ornaments
+pub async fn lockfile(
+    State(limit): State<UploadLimit>,
+    Query(params): Query<CanvasParams>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response, UploadError>
+{
+    // an explicit format wins over the Accept header
+    let format = params.format.unwrap_or_else(|| {
+        let accept = headers.get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if accept.contains("image/svg+xml") { CanvasFormat::Svg } else { CanvasFormat::Html }
+    });
+
+    let lockfiles = read_lockfiles(&mut multipart, &["lockfile"], MAX_CANVASES, limit).await?;
+    if lockfiles.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "missing lockfile field".to_owned()));
+    };
+
+    let mut canvases = Vec::new();
+    for (_, lockfile) in lockfiles {
+        let mut ornaments = Vec::new();
+
+        for p in lockfile.packages {
+            if let Some(checksum) = p.checksum {
+                let checksum = checksum.as_sha256()
+                    .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, format!("{} {}: checksum is not SHA-256", p.name, p.version)))?;
+
+                ornaments.push(Ornament {
+                    color: format!("#{:02x}{:02x}{:02x}", checksum[0], checksum[1], checksum[2]),
+                    top: checksum[3],
+                    left: checksum[4],
+                    name: p.name.to_string(),
+                    version: p.version.to_string(),
+                });
+            };
+        };
+        canvases.push(ornaments);
+    };
+
+    Ok(match format {
+        CanvasFormat::Html => HtmlTemplate(CanvasTemplate { canvases: &canvases }).into_response(),
+        CanvasFormat::Svg => (
+            [(header::CONTENT_TYPE, "image/svg+xml")],
+            render(&CanvasSvgTemplate {
+                width: canvases.len() * CANVAS_SIZE,
+                size: CANVAS_SIZE,
+                canvases: &canvases,
+            }).map_err(|status| (status, String::new()))?,
+        ).into_response(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// Fragment for htmx
+    #[default]
+    Html,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+/// Analyse the dependencies of an uploaded lockfile
+pub async fn lockfile_report(
+    State(limit): State<UploadLimit>,
+    Query(params): Query<ReportParams>,
+    mut multipart: Multipart,
+) -> Result<Response, UploadError>
+{
+    let lockfile = take_lockfile(&mut read_lockfiles(&mut multipart, &["lockfile"], 1, limit).await?, "lockfile")?;
+    let report = Report::new(&lockfile)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    Ok(match params.format {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Html => HtmlTemplate(ReportTemplate {
+            tree: flatten_tree(&report.tree, 0),
+            report: &report,
+        }).into_response(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "day_23/report.html")]
+struct ReportTemplate<'a> {
+    report: &'a Report,
+    /// Dependency tree as rows indented by depth
+    tree: Vec<(usize, &'a TreeNode)>,
+}
+
+fn flatten_tree(nodes: &[TreeNode], depth: usize) -> Vec<(usize, &TreeNode)> {
+    nodes.iter()
+        .flat_map(|node| std::iter::once((depth, node)).chain(flatten_tree(&node.dependencies, depth + 1)))
+        .collect()
+}
+
+/// Compare the lockfiles uploaded as `before` and `after`
+pub async fn lockfile_diff(
+    State(limit): State<UploadLimit>,
+    Query(params): Query<ReportParams>,
+    mut multipart: Multipart,
+) -> Result<Response, UploadError>
+{
+    let mut lockfiles = read_lockfiles(&mut multipart, &["before", "after"], 2, limit).await?;
+    let before = take_lockfile(&mut lockfiles, "before")?;
+    let after = take_lockfile(&mut lockfiles, "after")?;
+    let diff = Diff::new(&before, &after);
+
+    Ok(match params.format {
+        ReportFormat::Json => Json(diff).into_response(),
+        ReportFormat::Html => HtmlTemplate(DiffTemplate::new(&diff)).into_response(),
+    })
+}
+
+/// Changes of a diff as lines of text, by kind
+#[derive(Template)]
+#[template(path = "day_23/diff.html")]
+struct DiffTemplate<'a> {
+    diff: &'a Diff,
+    added: Vec<String>,
+    removed: Vec<String>,
+    upgraded: Vec<String>,
+    downgraded: Vec<String>,
+    checksum_changed: Vec<String>,
+    source_changed: Vec<String>,
+}
+
+impl<'a> DiffTemplate<'a> {
+    fn new(diff: &'a Diff) -> Self {
+        let package = |p: &PackageVersion| format!("{} {}", p.name, p.version);
+        let version_change = |c: &VersionChange| {
+            let line = format!("{} {} → {}", c.name, c.from, c.to);
+            if c.source_changed {
+                format!(
+                    "{}, source changed: {} → {}",
+                    line,
+                    c.from_source.as_deref().unwrap_or("none"),
+                    c.to_source.as_deref().unwrap_or("none"),
+                )
+            } else {
+                line
+            }
+        };
+        let change = |c: &Change| format!(
+            "{} {}: {} → {}",
+            c.name,
+            c.version,
+            c.from.as_deref().unwrap_or("none"),
+            c.to.as_deref().unwrap_or("none"),
+        );
+
+        Self {
+            diff,
+            added: diff.added.iter().map(package).collect(),
+            removed: diff.removed.iter().map(package).collect(),
+            upgraded: diff.upgraded.iter().map(version_change).collect(),
+            downgraded: diff.downgraded.iter().map(version_change).collect(),
+            checksum_changed: diff.checksum_changed.iter().map(change).collect(),
+            source_changed: diff.source_changed.iter().map(change).collect(),
+        }
+    }
+}
+
+/// Match the packages of an uploaded lockfile against the RustSec advisories, 503 without a database
+pub async fn lockfile_audit(
+    State(db): State<Option<Arc<AdvisoryDb>>>,
+    State(limit): State<UploadLimit>,
+    Query(params): Query<ReportParams>,
+    mut multipart: Multipart,
+) -> Result<Response, UploadError>
+{
+    let db = db.ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, "no advisory database is configured".to_owned()))?;
+    let lockfile = take_lockfile(&mut read_lockfiles(&mut multipart, &["lockfile"], 1, limit).await?, "lockfile")?;
+    let audit = db.audit(&lockfile);
+
+    Ok(match params.format {
+        ReportFormat::Json => Json(audit).into_response(),
+        ReportFormat::Html => HtmlTemplate(AuditTemplate { audit: &audit }).into_response(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "day_23/audit.html")]
+struct AuditTemplate<'a> {
+    audit: &'a Audit,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use axum::body::to_bytes;
+
+    use super::*;
+
+    /// Compare with `tests/snapshots/day_23/{name}`, rewritten instead when `UPDATE_SNAPSHOTS` is set
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests/snapshots/day_23", name].iter().collect();
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        };
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {}, run with UPDATE_SNAPSHOTS=1 to create it", path.display(), e));
+        assert_eq!(actual, expected, "{} is out of date", name);
+    }
+
+    const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    /// Lockfile of `(name, version, dependencies, checksum byte)`, packages with a checksum come from crates.io
+    fn lockfile(packages: &[(&str, &str, &[&str], Option<u8>)]) -> Lockfile {
+        let mut toml = "version = 3\n".to_owned();
+        for (name, version, dependencies, checksum) in packages {
+            toml += &format!("\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n", name, version);
+            if let Some(byte) = checksum {
+                toml += &format!("source = \"{}\"\nchecksum = \"{}\"\n", CRATES_IO, format!("{:02x}", byte).repeat(32));
+            };
+            if !dependencies.is_empty() {
+                toml += &format!("dependencies = {:?}\n", dependencies);
+            };
+        };
+        toml.parse().unwrap()
+    }
+
+    #[test]
+    fn star() {
+        assert_snapshot("star.html", &StarTemplate.render().unwrap());
+    }
+
+    #[test]
+    fn present() {
+        let present = |theme: Option<&str>| PresentTemplate {
+            color: "red".to_owned(),
+            next: "blue".to_owned(),
+            theme: theme.map(str::to_owned),
+        }.render().unwrap();
+        assert_snapshot("present.html", &present(None));
+        assert_snapshot("present_themed.html", &present(Some("winter")));
+    }
+
+    #[tokio::test]
+    async fn ornament() {
+        assert_snapshot("ornament_on.html", &OrnamentTemplate { n: 3, on: true }.render().unwrap());
+        assert_snapshot("ornament_off.html", &OrnamentTemplate { n: 3, on: false }.render().unwrap());
+        assert_snapshot("ornament_echo.html", &OrnamentEchoTemplate { n: "3".to_owned(), on: true, next_state: "off" }.render().unwrap());
+
+        // the event as it goes over the wire
+        let events = futures::stream::iter([Ok::<_, Infallible>(ornament_event(3, true))]);
+        let body = to_bytes(Sse::new(events).into_response().into_body(), usize::MAX).await.unwrap();
+        assert_snapshot("ornament_event.txt", std::str::from_utf8(&body).unwrap());
+    }
+
+    #[test]
+    fn canvas() {
+        let ornament = |byte: u8, name: &str| Ornament {
+            color: format!("#{:02x}{:02x}{:02x}", byte, byte, byte),
+            top: byte,
+            left: byte.wrapping_mul(2),
+            name: name.to_owned(),
+            version: "1.0.0".to_owned(),
+        };
+        let canvases = [vec![ornament(0x10, "heck"), ornament(0xa0, "itoa")], vec![ornament(0xff, "<serde>")]];
+
+        assert_snapshot("canvas.html", &CanvasTemplate { canvases: &canvases }.render().unwrap());
+        assert_snapshot("canvas.svg", &CanvasSvgTemplate {
+            width: canvases.len() * CANVAS_SIZE,
+            size: CANVAS_SIZE,
+            canvases: &canvases,
+        }.render().unwrap());
+    }
+
+    #[test]
+    fn report() {
+        let lockfile = lockfile(&[
+            ("app", "0.1.0", &["heck 0.4.2", "itoa"], None),
+            ("heck", "0.4.2", &[], Some(1)),
+            ("heck", "0.5.0", &[], Some(2)),
+            ("itoa", "1.0.0", &["heck 0.5.0"], Some(3)),
+        ]);
+        let report = Report::new(&lockfile).unwrap();
+        assert_snapshot("report.html", &ReportTemplate { tree: flatten_tree(&report.tree, 0), report: &report }.render().unwrap());
+    }
+
+    #[test]
+    fn diff() {
+        let before = lockfile(&[
+            ("heck", "0.4.2", &[], Some(1)),
+            ("itoa", "1.0.0", &[], Some(2)),
+            ("ryu", "1.0.0", &[], Some(3)),
+            ("serde", "1.0.0", &[], Some(4)),
+            ("toml", "0.8.0", &[], Some(7)),
+        ]);
+        let after = lockfile(&[
+            ("heck", "0.5.0", &[], Some(1)),
+            ("itoa", "1.0.0", &[], Some(5)),
+            ("serde", "1.0.0", &[], None),
+            ("toml", "0.8.1", &[], None),
+            ("zmij", "1.0.0", &[], Some(6)),
+        ]);
+        assert_snapshot("diff.html", &DiffTemplate::new(&Diff::new(&before, &after)).render().unwrap());
+        assert_snapshot("diff_empty.html", &DiffTemplate::new(&Diff::new(&before, &before)).render().unwrap());
+    }
+
+    #[test]
+    fn audit() {
+        let db = AdvisoryDb::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/advisory-db")).unwrap();
+        let lockfile = lockfile(&[
+            ("heck", "0.4.2", &[], Some(1)),
+            ("itoa", "1.0.0", &[], Some(2)),
+        ]);
+        assert_snapshot("audit.html", &AuditTemplate { audit: &db.audit(&lockfile) }.render().unwrap());
+    }
 }
@@ -1,7 +1,13 @@
-use std::str::FromStr;
-use axum::http::StatusCode;
+use std::{str::FromStr, sync::Arc};
+use axum::{extract::State, http::StatusCode};
 
-pub async fn manifest(body: String) -> Result<(StatusCode, String), (StatusCode, &'static str)> {
+use crate::config::Config;
+
+pub async fn manifest(
+    State(config): State<Arc<Config>>,
+    body: String,
+) -> Result<(StatusCode, String), (StatusCode, &'static str)>
+{
     // parse cargo manifest, return error if failed
     let manifest = cargo_manifest::Manifest::from_str(body.as_str()).map_err(|_| (
             StatusCode::BAD_REQUEST,
@@ -19,7 +25,7 @@ pub async fn manifest(body: String) -> Result<(StatusCode, String), (StatusCode,
             StatusCode::BAD_REQUEST,
             "Magic keyword not provided",
         ))?
-        .contains(&String::from("Christmas 2024")) {
+        .contains(&config.manifest.keyword) {
             return Err((
                 StatusCode::BAD_REQUEST,
                 "Magic keyword not provided",
@@ -50,7 +56,7 @@ pub async fn manifest(body: String) -> Result<(StatusCode, String), (StatusCode,
         ));
     };
 
-    let mut resp = String::from(format!("{}: {}", orders[0].0, orders[0].1));
+    let mut resp = format!("{}: {}", orders[0].0, orders[0].1);
     for (i, q) in orders.iter().skip(1) {
         resp.push_str(format!("\n{}: {}", i, q).as_str());
     };
@@ -1,17 +1,7 @@
-use lazy_static::lazy_static;
-use std::{sync::{Arc, Mutex}, time::Duration};
+use std::sync::{Arc, Mutex};
 use axum::{extract::{State, Json, rejection::JsonRejection}, response::{Response, IntoResponse}, http::StatusCode};
 
-lazy_static! {
-    pub static ref cow: Arc<Mutex<leaky_bucket::RateLimiter>> = Arc::new(Mutex::new(
-        leaky_bucket::RateLimiter::builder()
-            .initial(5)
-            .refill(1)
-            .max(5)
-            .interval(Duration::from_secs(1))
-            .build()
-    ));
-}
+use crate::config::Config;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct Payload {
@@ -22,7 +12,8 @@ pub struct Payload {
     pints: Option<f32>,
 }
 
-const LITERS_PER_GALLON: f32 = 3.785411784;
+// exact value, clippy rejects f32 literals with more digits than an f32 holds
+const LITERS_PER_GALLON: f32 = 3.785411784_f64 as f32;
 const LITRES_PER_PINT: f32 = 0.56826125;
 
 pub async fn milk(
@@ -84,6 +75,7 @@ pub async fn milk(
                             "Milk withdrawn\n",
                         ).into_response()
                     } else {
+                        tracing::info!("milk denied, bucket empty");
                         (
                             StatusCode::TOO_MANY_REQUESTS,
                             "No milk available\n",
@@ -100,15 +92,14 @@ pub async fn milk(
     }
 }
 
-pub async fn refill(State(milkee): State<Arc<Mutex<leaky_bucket::RateLimiter>>>) -> StatusCode {
+pub async fn refill(
+    State(milkee): State<Arc<Mutex<leaky_bucket::RateLimiter>>>,
+    State(config): State<Arc<Config>>,
+) -> StatusCode
+{
     let mut milkee = milkee.lock().unwrap();
 
-    *milkee = leaky_bucket::RateLimiter::builder()
-        .initial(5)
-        .refill(1)
-        .max(5)
-        .interval(Duration::from_secs(1))
-        .build();
+    *milkee = config.milk.bucket();
 
     StatusCode::OK
 }
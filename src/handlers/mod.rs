@@ -8,8 +8,8 @@ mod day_23;
 
 pub use day_2::{dest, key, dest_v6, key_v6};
 pub use day_5::manifest;
-pub use day_9::{milk, refill, cow};
-pub use day_12::{board, reset, place, random_board, singleton_board};
-pub use day_16::{wrap, unwrap, decode};
-pub use day_19::{clear_quotes, cite, remove, undo, draft};
-pub use day_23::{star, color, ornament, lockfile};
+pub use day_9::{milk, refill};
+pub use day_12::{play, board, reset, place, random_board, Board};
+pub use day_16::{wrap, unwrap, decode, SantaKeys};
+pub use day_19::{clear_quotes, cite, remove, undo, draft, patch, list, authors, author_quotes, tags, daily, random, audit, search, import, export, trash, restore, spawn_trash_purge, spawn_change_purge, changes};
+pub use day_23::{star, color, themes, theme_css, ornament, ornament_state, toggle_ornament, ornament_events, Ornaments, lockfile, lockfile_report, lockfile_diff, lockfile_audit, UploadLimit, MAX_CANVASES};
@@ -0,0 +1,149 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{DefaultBodyLimit, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post, delete, put, patch},
+    Router
+};
+use sqlx::PgPool;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
+
+use crate::{
+    config::Config,
+    state::{AppState, QuoteState, Quotes},
+    store::QuoteStore,
+};
+
+pub mod config;
+mod handlers;
+mod lockfile;
+pub mod logging;
+mod models;
+#[cfg(feature = "standalone")]
+pub mod standalone;
+pub mod state;
+mod store;
+mod templates;
+mod themes;
+mod validation;
+
+async fn hello_bird() -> &'static str {
+    "Hello, bird!"
+}
+
+async fn seek(State(config): State<Arc<Config>>) -> impl IntoResponse {
+    (
+        StatusCode::FOUND,
+        [(header::LOCATION, config.seek_url.clone())],
+    )
+}
+
+/// Day 19 routes served by any quote store
+fn quote_routes<S: QuoteStore, T: Clone + Send + Sync + 'static>(state: QuoteState<S>) -> Router<T> {
+    Router::new()
+        .route("/19/reset", post(handlers::clear_quotes::<S>))
+        .route("/19/cite/:id", get(handlers::cite::<S>))
+        .route("/19/remove/:id", delete(handlers::remove::<S>))
+        .route("/19/undo/:id", put(handlers::undo::<S>))
+        .route("/19/draft", post(handlers::draft::<S>))
+        .route("/19/quote/:id", patch(handlers::patch::<S>))
+        .route("/19/list", get(handlers::list::<S>))
+        .route("/19/authors", get(handlers::authors::<S>))
+        .route("/19/authors/:id/quotes", get(handlers::author_quotes::<S>))
+        .route("/19/tags", get(handlers::tags::<S>))
+        .route("/19/daily", get(handlers::daily::<S>))
+        .route("/19/random", get(handlers::random::<S>))
+        .route("/19/audit", get(handlers::audit::<S>))
+        .route("/19/search", get(handlers::search::<S>))
+        .route("/19/import", post(handlers::import::<S>))
+        .route("/19/export", get(handlers::export::<S>))
+        .route("/19/trash", get(handlers::trash::<S>))
+        .route("/19/restore/:id", post(handlers::restore::<S>))
+        .route("/19/changes", get(handlers::changes::<S>))
+        .with_state(state)
+}
+
+/// All routes over `state`, build a fresh `AppState` for an isolated instance
+pub fn app(state: AppState) -> Router {
+    let quote_routes = match &state.quotes {
+        Quotes::Postgres(store) => quote_routes(QuoteState::new(store.clone(), &state)),
+        Quotes::Memory(store) => quote_routes(QuoteState::new(store.clone(), &state)),
+    };
+
+    // room for as many lockfiles as each route accepts
+    let upload = |fields| DefaultBodyLimit::max(state.upload_limit.body_limit(fields));
+    let lockfile_routes = Router::new()
+        .route("/23/lockfile", post(handlers::lockfile).layer(upload(handlers::MAX_CANVASES)))
+        .route("/23/lockfile/report", post(handlers::lockfile_report).layer(upload(1)))
+        .route("/23/lockfile/diff", post(handlers::lockfile_diff).layer(upload(2)))
+        .route("/23/lockfile/audit", post(handlers::lockfile_audit).layer(upload(1)));
+
+    Router::new()
+        .route("/", get(hello_bird))
+        .route("/-1/seek", get(seek))
+        .route("/2/dest", get(handlers::dest))
+        .route("/2/key", get(handlers::key))
+        .route("/2/v6/dest", get(handlers::dest_v6))
+        .route("/2/v6/key", get(handlers::key_v6))
+        .route("/5/manifest", post(handlers::manifest))
+        .route("/9/milk", post(handlers::milk))
+        .route("/9/refill", post(handlers::refill))
+        .route("/12", get(handlers::play))
+        .route("/12/board", get(handlers::board))
+        .route("/12/reset", post(handlers::reset))
+        .route("/12/place/:team/:column", post(handlers::place))
+        .route("/12/random-board", get(handlers::random_board))
+        .route("/16/wrap", post(handlers::wrap))
+        .route("/16/unwrap", get(handlers::unwrap))
+        .route("/16/decode", post(handlers::decode))
+        .merge(quote_routes)
+        .nest_service("/assets", ServeDir::new("assets"))
+        .route("/23/star", get(handlers::star))
+        .route("/23/present/:color", get(handlers::color))
+        .route("/23/themes", get(handlers::themes))
+        .route("/23/themes/:name/style.css", get(handlers::theme_css))
+        .route("/23/ornament/:state/:n", get(handlers::ornament))
+        .route("/23/ornament/events", get(handlers::ornament_events))
+        .route("/23/ornament/:n", get(handlers::ornament_state))
+        .route("/23/ornament/:n/toggle", post(handlers::toggle_ornament))
+        .merge(lockfile_routes)
+        // last layer runs first: set the request ID, copy it to the response, then trace
+        .layer(TraceLayer::new_for_http()
+            .make_span_with(logging::request_span)
+            .on_response(logging::on_response)
+            // same rule as `on_response`: 5xx may be answers, failures are logged where they happen
+            .on_failure(()))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
+}
+
+/// Migrate the database, start background tasks and build the app
+pub async fn setup(config: Config, pool: PgPool) -> Router {
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to migrate database");
+
+    let state = AppState::new(config, pool).await;
+    let trash_retention = Duration::from_secs(state.config.quotes.trash_retention_days * 24 * 60 * 60);
+    let change_retention = Duration::from_secs(state.config.quotes.change_retention_days * 24 * 60 * 60);
+    match &state.quotes {
+        Quotes::Postgres(store) => {
+            handlers::spawn_trash_purge(store.clone(), trash_retention);
+            handlers::spawn_change_purge(store.clone(), change_retention);
+        },
+        Quotes::Memory(store) => {
+            handlers::spawn_trash_purge(store.clone(), trash_retention);
+            handlers::spawn_change_purge(store.clone(), change_retention);
+        },
+    };
+
+    app(state)
+}
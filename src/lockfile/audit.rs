@@ -0,0 +1,181 @@
+use std::{fmt, path::{Path, PathBuf}};
+
+use cargo_lock::Lockfile;
+use rustsec::{advisory::Informational, database::Query, osv::{ranges_for_advisory, OsvRange}, Collection, Database};
+use serde::Serialize;
+
+use super::package_id;
+
+/// Snapshot of the RustSec advisory database, a checkout of
+/// <https://github.com/rustsec/advisory-db> read once and never fetched
+pub struct AdvisoryDb {
+    db: Database,
+    len: usize,
+}
+
+#[derive(Debug)]
+pub enum AdvisoryDbError {
+    /// No directory at the configured path
+    Missing(PathBuf),
+    /// The directory has no advisories, most likely not a checkout of the database
+    Empty(PathBuf),
+    Load(rustsec::Error),
+}
+
+impl fmt::Display for AdvisoryDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdvisoryDbError::Missing(path) => write!(f, "no advisory database at {}", path.display()),
+            AdvisoryDbError::Empty(path) => write!(f, "no advisories in {}", path.display()),
+            AdvisoryDbError::Load(e) => write!(f, "cannot load advisory database: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AdvisoryDbError {}
+
+/// Advisories matching the packages of a lockfile
+#[derive(Debug, Serialize)]
+pub struct Audit {
+    /// Number of advisories in the database
+    pub database_advisories: usize,
+    pub vulnerabilities: Vec<Finding>,
+    /// Informational advisories, e.g. unmaintained or unsound crates
+    pub warnings: Vec<Finding>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// e.g. `RUSTSEC-2023-0071`
+    pub id: String,
+    pub aliases: Vec<String>,
+    pub package: String,
+    pub title: String,
+    pub date: String,
+    /// `none`, `low`, `medium`, `high` or `critical`, from the CVSS score if any
+    pub severity: Option<String>,
+    pub cvss_score: Option<f64>,
+    /// `unmaintained`, `unsound`, `notice`, ... for warnings
+    pub kind: Option<String>,
+    /// Affected versions, e.g. `>=0.4.0, <0.5.1`
+    pub affected: Vec<String>,
+    /// Every version is affected but these
+    pub patched: Vec<String>,
+    pub unaffected: Vec<String>,
+    pub url: Option<String>,
+}
+
+/// An affected range as a version requirement, `*` if every version is affected
+fn range(range: &OsvRange) -> String {
+    match (&range.introduced, &range.fixed) {
+        (Some(from), Some(to)) => format!(">={}, <{}", from, to),
+        (Some(from), None) => format!(">={}", from),
+        (None, Some(to)) => format!("<{}", to),
+        (None, None) => "*".to_owned(),
+    }
+}
+
+impl AdvisoryDb {
+    /// Load the advisories under `path`, which must have some
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AdvisoryDbError> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(AdvisoryDbError::Missing(path.to_owned()));
+        };
+
+        let db = Database::open(path).map_err(AdvisoryDbError::Load)?;
+        let len = db.iter().count();
+        if len == 0 {
+            return Err(AdvisoryDbError::Empty(path.to_owned()));
+        };
+        Ok(Self { db, len })
+    }
+
+    pub fn audit(&self, lockfile: &Lockfile) -> Audit {
+        let query = Query::new()
+            .collection(Collection::Crates)
+            .withdrawn(false);
+
+        let mut audit = Audit {
+            database_advisories: self.len,
+            vulnerabilities: Vec::new(),
+            warnings: Vec::new(),
+        };
+        for package in &lockfile.packages {
+            for advisory in self.db.query(&query.clone().package(package)) {
+                let metadata = &advisory.metadata;
+                let finding = Finding {
+                    id: metadata.id.to_string(),
+                    aliases: metadata.aliases.iter().map(ToString::to_string).collect(),
+                    package: package_id(package),
+                    title: metadata.title.clone(),
+                    date: metadata.date.as_str().to_owned(),
+                    severity: advisory.severity().map(|s| s.to_string()),
+                    cvss_score: metadata.cvss.as_ref().map(|c| c.score().value()),
+                    kind: metadata.informational.as_ref().map(|i| match i {
+                        Informational::Other(kind) => kind.clone(),
+                        other => other.as_str().to_owned(),
+                    }),
+                    affected: ranges_for_advisory(&advisory.versions).iter().map(range).collect(),
+                    patched: advisory.versions.patched().iter().map(ToString::to_string).collect(),
+                    unaffected: advisory.versions.unaffected().iter().map(ToString::to_string).collect(),
+                    url: metadata.url.as_ref().map(ToString::to_string),
+                };
+
+                if metadata.informational.is_some() {
+                    audit.warnings.push(finding);
+                } else {
+                    audit.vulnerabilities.push(finding);
+                };
+            };
+        };
+        audit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/advisory-db");
+
+    #[test]
+    fn open_needs_advisories() {
+        assert!(matches!(AdvisoryDb::open("no/such/dir"), Err(AdvisoryDbError::Missing(_))));
+        assert!(matches!(AdvisoryDb::open(concat!(env!("CARGO_MANIFEST_DIR"), "/templates")), Err(AdvisoryDbError::Empty(_))));
+        assert_eq!(AdvisoryDb::open(DB).unwrap().len, 2);
+    }
+
+    #[test]
+    fn findings_have_affected_ranges() {
+        let lockfile: Lockfile = r#"
+            version = 3
+
+            [[package]]
+            name = "heck"
+            version = "0.4.2"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+        "#.parse().unwrap();
+        let audit = AdvisoryDb::open(DB).unwrap().audit(&lockfile);
+
+        assert_eq!(audit.database_advisories, 2);
+        assert_eq!(audit.vulnerabilities.len(), 1);
+        let heck = &audit.vulnerabilities[0];
+        assert_eq!((heck.id.as_str(), heck.package.as_str()), ("RUSTSEC-2099-0001", "heck 0.4.2"));
+        assert_eq!(heck.affected, [">=0.4.0, <0.5.1"]);
+
+        assert_eq!(audit.warnings.len(), 1);
+        assert_eq!(audit.warnings[0].kind.as_deref(), Some("unmaintained"));
+        assert_eq!(audit.warnings[0].affected, ["*"]);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cargo_lock::{Lockfile, Package, Version};
+use serde::Serialize;
+
+/// What changed between two lockfiles
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    pub added: Vec<PackageVersion>,
+    pub removed: Vec<PackageVersion>,
+    pub upgraded: Vec<VersionChange>,
+    pub downgraded: Vec<VersionChange>,
+    /// Same version with another checksum, the published crate may have been tampered with
+    pub checksum_changed: Vec<Change>,
+    /// Same version from another source, e.g. crates.io replaced by a git fork
+    pub source_changed: Vec<Change>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageVersion {
+    pub name: String,
+    pub version: String,
+}
+
+/// A crate at another version, possibly from another source, `None` for path dependencies
+#[derive(Debug, Serialize)]
+pub struct VersionChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    pub from_source: Option<String>,
+    pub to_source: Option<String>,
+    /// The new version comes from elsewhere, e.g. crates.io replaced by a git fork
+    pub source_changed: bool,
+}
+
+/// A field of a package that changed at the same version, `None` if absent
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub name: String,
+    pub version: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Source of a package as written in the lockfile, `None` for path dependencies
+type Source = Option<String>;
+
+/// Versions of a crate, then packages by source: a lockfile may have a crate at the same
+/// version from several sources, e.g. crates.io and a git fork
+type Versions<'a> = BTreeMap<&'a Version, BTreeMap<Source, &'a Package>>;
+
+/// Packages by name, then version, then source
+fn by_name(lockfile: &Lockfile) -> BTreeMap<&str, Versions<'_>> {
+    let mut packages: BTreeMap<&str, Versions<'_>> = BTreeMap::new();
+    for p in &lockfile.packages {
+        packages.entry(p.name.as_str())
+            .or_default()
+            .entry(&p.version)
+            .or_default()
+            .insert(p.source.as_ref().map(ToString::to_string), p);
+    };
+    packages
+}
+
+/// Packages at versions in `a` but not in `b`
+fn only_in<'a>(a: &Versions<'a>, b: &Versions<'a>) -> Vec<&'a Package> {
+    a.iter()
+        .filter(|(v, _)| !b.contains_key(*v))
+        .flat_map(|(_, sources)| sources.values().copied())
+        .collect()
+}
+
+fn source(p: &Package) -> Source {
+    p.source.as_ref().map(ToString::to_string)
+}
+
+impl Diff {
+    /// Record every field that differs between two packages at the same version
+    fn compare(&mut self, p: &Package, q: &Package) {
+        let change = |from: Option<String>, to: Option<String>| Change {
+            name: p.name.to_string(),
+            version: p.version.to_string(),
+            from,
+            to,
+        };
+
+        let (from, to) = (source(p), source(q));
+        if from != to {
+            self.source_changed.push(change(from, to));
+        };
+        if p.checksum != q.checksum {
+            self.checksum_changed.push(change(
+                p.checksum.as_ref().map(ToString::to_string),
+                q.checksum.as_ref().map(ToString::to_string),
+            ));
+        };
+    }
+
+    /// Packages at the same version are compared source by source, then those only in one
+    /// of the lockfiles are paired in order into source changes.
+    /// Versions of a crate only in one of the lockfiles are paired in order, lowest first,
+    /// into upgrades and downgrades, which note a change of source, the rest are added or removed
+    pub fn new(before: &Lockfile, after: &Lockfile) -> Self {
+        let before = by_name(before);
+        let after = by_name(after);
+        let names: BTreeSet<&str> = before.keys().chain(after.keys()).copied().collect();
+        let empty = BTreeMap::new();
+
+        let mut diff = Self::default();
+        for name in names {
+            let old = before.get(name).unwrap_or(&empty);
+            let new = after.get(name).unwrap_or(&empty);
+            let version = |v: &Version| PackageVersion { name: name.to_owned(), version: v.to_string() };
+
+            for (v, old_sources) in old {
+                let Some(new_sources) = new.get(v) else { continue };
+
+                for (source, p) in old_sources {
+                    if let Some(q) = new_sources.get(source) {
+                        diff.compare(p, q);
+                    };
+                };
+                let gone: Vec<&Package> = old_sources.iter().filter(|(s, _)| !new_sources.contains_key(*s)).map(|(_, p)| *p).collect();
+                let came: Vec<&Package> = new_sources.iter().filter(|(s, _)| !old_sources.contains_key(*s)).map(|(_, p)| *p).collect();
+                for (p, q) in gone.iter().zip(&came) {
+                    diff.compare(p, q);
+                };
+                diff.removed.extend(gone.iter().skip(came.len()).map(|p| version(&p.version)));
+                diff.added.extend(came.iter().skip(gone.len()).map(|p| version(&p.version)));
+            };
+
+            let gone = only_in(old, new);
+            let came = only_in(new, old);
+            for (p, q) in gone.iter().zip(&came) {
+                let change = VersionChange {
+                    name: name.to_owned(),
+                    from: p.version.to_string(),
+                    to: q.version.to_string(),
+                    from_source: source(p),
+                    to_source: source(q),
+                    source_changed: p.source != q.source,
+                };
+                if q.version > p.version {
+                    diff.upgraded.push(change);
+                } else {
+                    diff.downgraded.push(change);
+                };
+            };
+            diff.removed.extend(gone.iter().skip(came.len()).map(|p| version(&p.version)));
+            diff.added.extend(came.iter().skip(gone.len()).map(|p| version(&p.version)));
+        };
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+            && self.checksum_changed.is_empty()
+            && self.source_changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+    const FORK: &str = "git+https://github.com/elves/serde?branch=fix#0123456789abcdef0123456789abcdef01234567";
+    const A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    /// A lockfile of `(name, version, source, checksum)`
+    fn lockfile(packages: &[(&str, &str, Option<&str>, Option<&str>)]) -> Lockfile {
+        let mut text = "version = 3\n".to_owned();
+        for (name, version, source, checksum) in packages {
+            text.push_str(&format!("\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n", name, version));
+            if let Some(s) = source {
+                text.push_str(&format!("source = \"{}\"\n", s));
+            };
+            if let Some(c) = checksum {
+                text.push_str(&format!("checksum = \"{}\"\n", c));
+            };
+        };
+        text.parse().unwrap()
+    }
+
+    fn changes(changes: &[Change]) -> Vec<(&str, &str, Option<&str>, Option<&str>)> {
+        changes.iter()
+            .map(|c| (c.name.as_str(), c.version.as_str(), c.from.as_deref(), c.to.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn versions() {
+        let before = lockfile(&[
+            ("rand", "0.8.5", Some(CRATES_IO), Some(A)),
+            ("syn", "2.0.1", Some(CRATES_IO), Some(A)),
+            ("log", "0.4.0", Some(CRATES_IO), Some(A)),
+        ]);
+        let after = lockfile(&[
+            ("rand", "0.9.0", Some(CRATES_IO), Some(A)),
+            ("syn", "1.0.0", Some(CRATES_IO), Some(A)),
+            ("serde", "1.0.0", Some(CRATES_IO), Some(A)),
+        ]);
+        let diff = Diff::new(&before, &after);
+
+        assert_eq!(diff.upgraded.iter().map(|c| (c.name.as_str(), c.from.as_str(), c.to.as_str())).collect::<Vec<_>>(), [("rand", "0.8.5", "0.9.0")]);
+        assert_eq!(diff.downgraded.iter().map(|c| (c.name.as_str(), c.from.as_str(), c.to.as_str())).collect::<Vec<_>>(), [("syn", "2.0.1", "1.0.0")]);
+        assert_eq!(diff.added.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["serde"]);
+        assert_eq!(diff.removed.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["log"]);
+        assert!(Diff::new(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn every_changed_field() {
+        let before = lockfile(&[("serde", "1.0.0", Some(CRATES_IO), Some(A))]);
+        let after = lockfile(&[("serde", "1.0.0", Some(FORK), Some(B))]);
+        let diff = Diff::new(&before, &after);
+
+        assert_eq!(changes(&diff.source_changed), [("serde", "1.0.0", Some(CRATES_IO), Some(FORK))]);
+        assert_eq!(changes(&diff.checksum_changed), [("serde", "1.0.0", Some(A), Some(B))]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn version_and_source_change() {
+        let before = lockfile(&[
+            ("serde", "1.0.0", Some(CRATES_IO), Some(A)),
+            ("syn", "2.0.0", Some(CRATES_IO), Some(A)),
+        ]);
+        let after = lockfile(&[
+            ("serde", "1.0.1", Some(FORK), None),
+            ("syn", "2.0.1", Some(CRATES_IO), Some(B)),
+        ]);
+        let diff = Diff::new(&before, &after);
+
+        let upgrades: Vec<_> = diff.upgraded.iter()
+            .map(|c| (c.name.as_str(), c.from_source.as_deref(), c.to_source.as_deref(), c.source_changed))
+            .collect();
+        assert_eq!(upgrades, [("serde", Some(CRATES_IO), Some(FORK), true), ("syn", Some(CRATES_IO), Some(CRATES_IO), false)]);
+        assert!(diff.source_changed.is_empty());
+    }
+
+    #[test]
+    fn same_version_from_several_sources() {
+        let before = lockfile(&[
+            ("serde", "1.0.0", Some(CRATES_IO), Some(A)),
+            ("serde", "1.0.0", Some(FORK), None),
+        ]);
+        let after = lockfile(&[("serde", "1.0.0", Some(CRATES_IO), Some(B))]);
+        let diff = Diff::new(&before, &after);
+
+        // the crates.io package is compared with itself, the fork is gone
+        assert_eq!(changes(&diff.checksum_changed), [("serde", "1.0.0", Some(A), Some(B))]);
+        assert!(diff.source_changed.is_empty());
+        assert_eq!(diff.removed.iter().map(|p| (p.name.as_str(), p.version.as_str())).collect::<Vec<_>>(), [("serde", "1.0.0")]);
+
+        let diff = Diff::new(&after, &before);
+        assert_eq!(diff.added.iter().map(|p| (p.name.as_str(), p.version.as_str())).collect::<Vec<_>>(), [("serde", "1.0.0")]);
+    }
+}
@@ -0,0 +1,25 @@
+mod audit;
+mod diff;
+mod report;
+
+use cargo_lock::Package;
+
+pub use audit::{AdvisoryDb, Audit};
+pub use diff::{Change, Diff, PackageVersion, VersionChange};
+pub use report::{Report, TreeNode};
+
+/// Name and version of a package, e.g. `serde 1.0.215`
+pub fn package_id(package: &Package) -> String {
+    format!("{} {}", package.name, package.version)
+}
+
+/// Where a package comes from: `crates.io`, `git`, `path`, or the name of another registry
+pub fn source_name(package: &Package) -> String {
+    match &package.source {
+        // workspace members have no source
+        None => "path".to_owned(),
+        Some(s) if s.is_git() => "git".to_owned(),
+        Some(s) if s.is_path() => "path".to_owned(),
+        Some(s) => s.display_registry_name(),
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use cargo_lock::{
+    dependency::{graph::{EdgeDirection, NodeIndex}, Tree},
+    Lockfile,
+};
+use serde::Serialize;
+
+use super::{package_id, source_name};
+
+/// What a lockfile says about its dependencies
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub packages: usize,
+    /// Package count by `source_name`
+    pub sources: BTreeMap<String, usize>,
+    /// Versions of crates present in more than one version
+    pub duplicates: BTreeMap<String, Vec<String>>,
+    /// Packages nothing depends on, usually the workspace members
+    pub roots: Vec<String>,
+    /// Fewest dependency hops from a root to each package
+    pub depths: BTreeMap<String, usize>,
+    pub max_depth: usize,
+    /// Dependencies from each root, a package already shown is not expanded again
+    pub tree: Vec<TreeNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<TreeNode>,
+}
+
+impl Report {
+    /// Fails if a package depends on something missing from the lockfile
+    pub fn new(lockfile: &Lockfile) -> Result<Self, cargo_lock::Error> {
+        let tree = Tree::new(lockfile)?;
+        let graph = tree.graph();
+
+        let mut sources = BTreeMap::new();
+        let mut versions: BTreeMap<String, BTreeSet<_>> = BTreeMap::new();
+        for p in &lockfile.packages {
+            *sources.entry(source_name(p)).or_default() += 1;
+            versions.entry(p.name.to_string()).or_default().insert(&p.version);
+        };
+        let duplicates = versions.into_iter()
+            .filter(|(_, v)| v.len() > 1)
+            .map(|(name, v)| (name, v.into_iter().map(ToString::to_string).collect()))
+            .collect();
+
+        // sorted so that the tree reads the same every time
+        let children = |node: NodeIndex| {
+            let mut deps: Vec<NodeIndex> = graph.neighbors_directed(node, EdgeDirection::Outgoing).collect();
+            deps.sort_by(|a, b| (&graph[*a].name, &graph[*a].version).cmp(&(&graph[*b].name, &graph[*b].version)));
+            deps.dedup();
+            deps
+        };
+        let mut roots = tree.roots();
+        roots.sort_by_key(|r| package_id(&graph[*r]));
+
+        // breadth-first from every root at once
+        let mut depth_of: HashMap<NodeIndex, usize> = roots.iter().map(|r| (*r, 0)).collect();
+        let mut queue: VecDeque<NodeIndex> = roots.iter().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            let depth = depth_of[&node] + 1;
+            for dep in children(node) {
+                depth_of.entry(dep).or_insert_with(|| {
+                    queue.push_back(dep);
+                    depth
+                });
+            };
+        };
+        let depths: BTreeMap<String, usize> = depth_of.into_iter()
+            .map(|(node, depth)| (package_id(&graph[node]), depth))
+            .collect();
+
+        let mut seen = HashSet::new();
+        let tree = roots.iter()
+            .map(|r| Self::subtree(*r, &children, &mut seen, graph))
+            .collect();
+
+        Ok(Self {
+            packages: lockfile.packages.len(),
+            sources,
+            duplicates,
+            roots: roots.iter().map(|r| package_id(&graph[*r])).collect(),
+            max_depth: depths.values().copied().max().unwrap_or(0),
+            depths,
+            tree,
+        })
+    }
+
+    fn subtree(
+        node: NodeIndex,
+        children: &impl Fn(NodeIndex) -> Vec<NodeIndex>,
+        seen: &mut HashSet<NodeIndex>,
+        graph: &cargo_lock::dependency::graph::Graph,
+    ) -> TreeNode
+    {
+        let package = &graph[node];
+        let repeated = !seen.insert(node);
+        let dependencies = if repeated {
+            Vec::new()
+        } else {
+            children(node).into_iter()
+                .map(|dep| Self::subtree(dep, children, seen, graph))
+                .collect()
+        };
+
+        TreeNode {
+            name: package.name.to_string(),
+            version: package.version.to_string(),
+            repeated,
+            dependencies,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use axum::{extract::MatchedPath, http::{Request, Response}};
+use tracing::{field::Empty, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Install the global subscriber, a no-op if one is already set
+pub fn init(config: &LogConfig) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.filter));
+
+    let _ = match config.format {
+        LogFormat::Human => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).try_init(),
+    };
+}
+
+/// Span of a request, `X-Request-Id` is already set by then
+pub fn request_span<B>(req: &Request<B>) -> Span {
+    let route = req.extensions().get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+    let request_id = req.headers().get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!("request",
+        method = %req.method(),
+        route,
+        request_id,
+        status = Empty,
+        latency_ms = Empty,
+    )
+}
+
+/// Record status and latency on the request span
+///
+/// Every response is logged at INFO whatever its status: some 5xx are answers, like the 503
+/// of a full Day 12 board, and actual failures are logged where they happen.
+pub fn on_response<B>(res: &Response<B>, latency: Duration, span: &Span) {
+    span.record("status", res.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    tracing::info!("finished");
+}
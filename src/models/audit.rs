@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::{Uuid, chrono::{DateTime, Local}};
+
+/// Who is making a change, recorded with it in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditContext {
+    /// `sub` claim of the caller's token, or `anonymous`
+    pub actor: String,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl Default for AuditContext {
+    fn default() -> Self {
+        Self {
+            actor: "anonymous".to_owned(),
+            request_id: None,
+            ip: None,
+        }
+    }
+}
+
+/// A row of `quote_audit`
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub quote_id: Option<Uuid>,
+    /// One of `draft`, `undo`, `remove` or `clear`
+    pub action: String,
+    pub actor: String,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub at: DateTime<Local>,
+}
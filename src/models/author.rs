@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::{Uuid, chrono::{DateTime, Local}};
+
+/// An author with aggregates over their live quotes
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Author {
+    pub id: Uuid,
+    /// Display name as first seen
+    pub name: String,
+    pub quote_count: i64,
+    pub latest_quote_at: Option<DateTime<Local>>,
+}
@@ -1,3 +1,11 @@
+mod audit;
+mod author;
 mod quote;
+mod quote_change;
+mod tag;
 
-pub use quote::Quote;
+pub use audit::{AuditContext, AuditEntry};
+pub use author::Author;
+pub use quote::{Quote, QuoteHit};
+pub use quote_change::QuoteChange;
+pub use tag::Tag;
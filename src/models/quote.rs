@@ -1,11 +1,34 @@
 use serde::{Deserialize, Serialize};
 use sqlx::types::{Uuid, chrono::{DateTime, Local}};
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Quote {
     pub id: Uuid,
     pub author: String,
+    /// Author resolved from `author`, ignoring case and whitespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub author_id: Option<Uuid>,
     pub quote: String,
     pub created_at: DateTime<Local>,
     pub version: i32,
+    /// Normalized tag names, sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[sqlx(default)]
+    pub tags: Vec<String>,
+    /// Set when the quote is in the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub deleted_at: Option<DateTime<Local>>,
+}
+
+/// A full-text search result
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct QuoteHit {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub quote: Quote,
+    pub rank: f32,
+    /// Excerpt of the quote, an HTML fragment in responses (see `store::QuoteStore::search`)
+    pub snippet: String,
 }
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::{Uuid, chrono::{DateTime, Local}};
+
+/// A row of `quote_changes`, also the payload of `quote_changes` notifications
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuoteChange {
+    pub seq: i64,
+    pub quote_id: Uuid,
+    /// One of `draft`, `undo`, `remove`, `restore` or `purge`
+    pub op: String,
+    pub version: i32,
+    pub changed_at: DateTime<Local>,
+}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// A tag with the number of live quotes carrying it
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Tag {
+    pub name: String,
+    pub quote_count: i64,
+}
@@ -0,0 +1,72 @@
+use std::{net::SocketAddr, time::Duration};
+
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::config::Config;
+
+/// Serve the app with plain tokio, outside of the Shuttle runtime
+pub async fn run() {
+    let config_file = std::env::var("CONFIG_FILE").unwrap_or_else(|_| "config.toml".to_owned());
+    let config = Config::load(&config_file, |_| None)
+        .expect("Failed to load config");
+    crate::logging::init(&config.log);
+    if config.server.database_url.is_empty() {
+        panic!("DATABASE_URL must be set in standalone mode");
+    };
+
+    let pool = PgPool::connect(&config.server.database_url)
+        .await
+        .expect("Failed to connect to database");
+    let bind = config.server.bind;
+    let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+    let app = crate::setup(config, pool).await;
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .expect("Failed to bind");
+    tracing::info!(%bind, "listening");
+
+    // stop accepting on the first signal, then give in-flight requests `grace` to finish,
+    // long-lived event streams would otherwise hold the server open
+    let (stopping, mut stopped) = watch::channel(false);
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down, draining in-flight requests");
+            let _ = stopping.send(true);
+        });
+    let deadline = async move {
+        let _ = stopped.wait_for(|s| *s).await;
+        tokio::time::sleep(grace).await;
+    };
+
+    tokio::select! {
+        res = server => res.expect("Server error"),
+        _ = deadline => tracing::warn!(?grace, "requests still in flight after the grace period, closing them"),
+    };
+}
+
+/// SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    };
+}
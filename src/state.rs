@@ -0,0 +1,143 @@
+use std::sync::{Arc, Mutex};
+
+use axum::extract::FromRef;
+use rand::{rngs::StdRng, SeedableRng};
+use sqlx::PgPool;
+
+use crate::{
+    config::{Config, QuoteStoreKind},
+    handlers::{Board, Ornaments, SantaKeys, UploadLimit},
+    lockfile::AdvisoryDb,
+    store::{MemoryQuoteStore, PgQuoteStore, QuoteStore},
+    themes::Themes,
+    validation::QuoteLimits,
+};
+
+/// Everything shared by the handlers, each of which extracts only the parts it needs
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    #[from_ref(skip)]
+    pub quotes: Quotes,
+    pub quote_limits: Arc<QuoteLimits>,
+    /// Day 9 milk bucket
+    pub milk: Arc<Mutex<leaky_bucket::RateLimiter>>,
+    pub board: Arc<Mutex<Board>>,
+    pub rng: Arc<Mutex<StdRng>>,
+    pub santa_keys: Arc<SantaKeys>,
+    pub themes: Arc<Themes>,
+    pub ornaments: Arc<Ornaments>,
+    /// Day 23 audits are unavailable without a readable advisory database
+    pub advisory_db: Option<Arc<AdvisoryDb>>,
+    pub upload_limit: UploadLimit,
+}
+
+/// Quote store selected by `quotes.store`
+#[derive(Clone)]
+pub enum Quotes {
+    Postgres(Arc<PgQuoteStore>),
+    Memory(Arc<MemoryQuoteStore>),
+}
+
+impl AppState {
+    /// Fresh state for `config`: empty board and ornaments, full milk bucket, reseeded RNG,
+    /// with the Postgres change feed and the Day 16 key watcher running
+    pub async fn new(config: Config, pool: PgPool) -> Self {
+        let quotes = match config.quotes.store {
+            QuoteStoreKind::Postgres => {
+                let store = PgQuoteStore::new(Arc::new(pool));
+                store.listen()
+                    .await
+                    .expect("Failed to listen for quote changes");
+                Quotes::Postgres(Arc::new(store))
+            },
+            QuoteStoreKind::Memory => Quotes::Memory(Arc::new(MemoryQuoteStore::new())),
+        };
+
+        let state = Self::with_quotes(config, quotes);
+        state.santa_keys.watch()
+            .expect("Failed to watch Day 16 keys");
+        state
+    }
+
+    /// Fresh state for `config` on an in-memory quote store, whatever `quotes.store` says,
+    /// without a database or the Day 16 key watcher
+    pub fn for_tests(config: Config) -> Self {
+        Self::with_quotes(config, Quotes::Memory(Arc::new(MemoryQuoteStore::new())))
+    }
+
+    fn with_quotes(config: Config, quotes: Quotes) -> Self {
+        let config = Arc::new(config);
+
+        let santa_keys = SantaKeys::load(&config.gift.santa_keys_dir, &config.gift.santa_key_file)
+            .expect("Failed to load Day 16 keys");
+        let themes = Themes::load(&config.themes.file)
+            .expect("Failed to load Day 23 themes");
+        let advisory_db = match AdvisoryDb::open(&config.lockfile.advisory_db) {
+            Ok(db) => Some(Arc::new(db)),
+            Err(e) => {
+                tracing::warn!(error = %e, "no RustSec advisory database, lockfile audits are disabled");
+                None
+            },
+        };
+
+        Self {
+            quotes,
+            quote_limits: Arc::new(config.quote_limits()),
+            milk: Arc::new(Mutex::new(config.milk.bucket())),
+            board: Arc::new(Mutex::new(Board::default())),
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(config.rng_seed))),
+            santa_keys,
+            themes: Arc::new(themes),
+            ornaments: Ornaments::new(),
+            advisory_db,
+            upload_limit: UploadLimit { max_bytes: config.lockfile.max_bytes },
+            config,
+        }
+    }
+}
+
+/// State of the Day 19 routes served by the quote store `S`
+pub struct QuoteState<S> {
+    pub store: Arc<S>,
+    pub limits: Arc<QuoteLimits>,
+    pub config: Arc<Config>,
+}
+
+impl<S> QuoteState<S> {
+    pub fn new(store: Arc<S>, state: &AppState) -> Self {
+        Self {
+            store,
+            limits: state.quote_limits.clone(),
+            config: state.config.clone(),
+        }
+    }
+}
+
+impl<S> Clone for QuoteState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            limits: self.limits.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S: QuoteStore> FromRef<QuoteState<S>> for Arc<S> {
+    fn from_ref(state: &QuoteState<S>) -> Self {
+        state.store.clone()
+    }
+}
+
+impl<S> FromRef<QuoteState<S>> for Arc<QuoteLimits> {
+    fn from_ref(state: &QuoteState<S>) -> Self {
+        state.limits.clone()
+    }
+}
+
+impl<S> FromRef<QuoteState<S>> for Arc<Config> {
+    fn from_ref(state: &QuoteState<S>) -> Self {
+        state.config.clone()
+    }
+}
@@ -0,0 +1,338 @@
+//! The `QuoteStore` contract, checked against every store
+
+use std::{sync::Arc, time::Duration};
+
+use futures::{Stream, StreamExt, TryStreamExt};
+use sqlx::{types::{chrono::Local, Uuid}, PgPool};
+
+use crate::models::{AuditContext, QuoteChange};
+use super::{
+    AuditFilter, ImportedQuote, MemoryQuoteStore, PgQuoteStore, QuoteFilter, QuoteStore,
+    SearchQuery, StoreError, TagFilter, HIGHLIGHT_END, HIGHLIGHT_START,
+};
+
+fn ctx(actor: &str) -> AuditContext {
+    AuditContext {
+        actor: actor.to_owned(),
+        request_id: Some("req".to_owned()),
+        ip: Some("127.0.0.1".to_owned()),
+    }
+}
+
+fn strings(s: &[&str]) -> Vec<String> {
+    s.iter().map(|s| (*s).to_owned()).collect()
+}
+
+/// Ops of the changes to `id` in `changes`, in order
+fn ops(changes: &[QuoteChange], id: Uuid) -> Vec<String> {
+    changes.iter()
+        .filter(|c| c.quote_id == id)
+        .map(|c| c.op.clone())
+        .collect()
+}
+
+/// Check every part of the contract on an empty store
+pub async fn conformance<S: QuoteStore>(store: S) {
+    crud(&store).await;
+    listing(&store).await;
+    trash(&store).await;
+    search(&store).await;
+    import_export(&store).await;
+    changes(&store).await;
+    clear(&store).await;
+}
+
+async fn crud<S: QuoteStore>(store: &S) {
+    let q = store.create(&ctx("santa"), "Santa".to_owned(), "Ho ho ho".to_owned(), strings(&[" Jolly", "jolly", ""])).await.unwrap();
+    assert_eq!(q.version, 1);
+    assert_eq!(q.tags, strings(&["jolly"]));
+    assert!(q.author_id.is_some());
+    assert!(q.deleted_at.is_none());
+
+    let got = store.get(q.id).await.unwrap();
+    assert_eq!((got.author.as_str(), got.quote.as_str(), got.created_at), ("Santa", "Ho ho ho", q.created_at));
+
+    // tags are kept unless given
+    let updated = store.update(&ctx("elf"), q.id, None, "santa ".to_owned(), "Ho ho ho!".to_owned(), None).await.unwrap();
+    assert_eq!(updated.version, 2);
+    assert_eq!(updated.created_at, q.created_at);
+    assert_eq!(updated.tags, strings(&["jolly"]));
+    assert_eq!(updated.author_id, q.author_id);
+    let updated = store.update(&ctx("elf"), q.id, None, "Santa".to_owned(), "Ho ho ho!".to_owned(), Some(Vec::new())).await.unwrap();
+    assert_eq!(updated.version, 3);
+    assert!(updated.tags.is_empty());
+
+    // stale versions are refused
+    assert!(matches!(store.update(&ctx("elf"), q.id, Some(2), "Santa".to_owned(), "Stale".to_owned(), None).await, Err(StoreError::Conflict)));
+    let updated = store.update(&ctx("elf"), q.id, Some(3), "Santa".to_owned(), "Ho ho ho!".to_owned(), None).await.unwrap();
+    assert_eq!(updated.version, 4);
+
+    assert!(matches!(store.get(Uuid::new_v4()).await, Err(StoreError::NotFound)));
+    assert!(matches!(store.update(&ctx("elf"), Uuid::new_v4(), None, "a".to_owned(), "b".to_owned(), None).await, Err(StoreError::NotFound)));
+    assert!(matches!(store.delete(&ctx("elf"), Uuid::new_v4()).await, Err(StoreError::NotFound)));
+
+    let deleted = store.delete(&ctx("grinch"), q.id).await.unwrap();
+    assert!(deleted.deleted_at.is_some());
+    assert!(matches!(store.get(q.id).await, Err(StoreError::NotFound)));
+    assert!(matches!(store.delete(&ctx("grinch"), q.id).await, Err(StoreError::NotFound)));
+
+    let filter = AuditFilter { quote_id: Some(q.id), ..AuditFilter::default() };
+    let log = store.audit_log(&filter, 0, 10).await.unwrap();
+    let actions: Vec<(&str, &str)> = log.iter().map(|e| (e.action.as_str(), e.actor.as_str())).collect();
+    assert_eq!(actions, [("remove", "grinch"), ("undo", "elf"), ("undo", "elf"), ("undo", "elf"), ("draft", "santa")]);
+    assert_eq!(log[4].request_id.as_deref(), Some("req"));
+    assert!(log[4].before.is_none() && log[4].after.is_some());
+
+    let by_elf = AuditFilter { quote_id: Some(q.id), actor: Some("elf".to_owned()), ..AuditFilter::default() };
+    assert_eq!(store.audit_log(&by_elf, 1, 10).await.unwrap().len(), 2);
+
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+async fn listing<S: QuoteStore>(store: &S) {
+    let a = store.create(&ctx("test"), "Rudolph".to_owned(), "Red nose".to_owned(), strings(&["glow", "deer"])).await.unwrap();
+    let b = store.create(&ctx("test"), " rudolph  ".to_owned(), "Bright".to_owned(), strings(&["glow"])).await.unwrap();
+    let c = store.create(&ctx("test"), "Dasher".to_owned(), "Fast".to_owned(), strings(&["deer"])).await.unwrap();
+
+    let all = store.list(0, 10, &QuoteFilter::default()).await.unwrap();
+    assert_eq!(all.iter().map(|q| q.id).collect::<Vec<_>>(), [a.id, b.id, c.id]);
+    assert_eq!(store.list(1, 1, &QuoteFilter::default()).await.unwrap()[0].id, b.id);
+    assert_eq!(store.count(&QuoteFilter::default()).await.unwrap(), 3);
+
+    let by_author = QuoteFilter { author: Some("RUDOLPH".to_owned()), ..QuoteFilter::default() };
+    assert_eq!(store.count(&by_author).await.unwrap(), 2);
+    let any = QuoteFilter { author: None, tags: TagFilter::new(strings(&["glow", "deer"]), false) };
+    assert_eq!(store.count(&any).await.unwrap(), 3);
+    let all_tags = QuoteFilter { author: None, tags: TagFilter::new(strings(&["GLOW", "deer"]), true) };
+    assert_eq!(store.list(0, 10, &all_tags).await.unwrap().iter().map(|q| q.id).collect::<Vec<_>>(), [a.id]);
+
+    // same author ignoring case and whitespace, named as first seen, kept without quotes
+    assert_eq!(a.author_id, b.author_id);
+    let authors = store.authors().await.unwrap();
+    let names: Vec<(&str, i64)> = authors.iter().map(|a| (a.name.as_str(), a.quote_count)).collect();
+    assert_eq!(names, [("Dasher", 1), ("Rudolph", 2), ("Santa", 0)]);
+    assert_eq!(authors[1].latest_quote_at, Some(b.created_at));
+
+    let rudolph = a.author_id.unwrap();
+    let quotes = store.author_quotes(rudolph, 0, 10).await.unwrap();
+    assert_eq!(quotes.iter().map(|q| q.id).collect::<Vec<_>>(), [a.id, b.id]);
+    assert!(matches!(store.author_quotes(Uuid::new_v4(), 0, 10).await, Err(StoreError::NotFound)));
+
+    let tags = store.tags().await.unwrap();
+    let tags: Vec<(&str, i64)> = tags.iter().map(|t| (t.name.as_str(), t.quote_count)).collect();
+    assert_eq!(tags, [("deer", 2), ("glow", 2)]);
+
+    // only live quotes count
+    store.delete(&ctx("test"), c.id).await.unwrap();
+    assert_eq!(store.count(&QuoteFilter::default()).await.unwrap(), 2);
+    let tags = store.tags().await.unwrap();
+    assert_eq!(tags.iter().map(|t| (t.name.as_str(), t.quote_count)).collect::<Vec<_>>(), [("deer", 1), ("glow", 2)]);
+    let authors = store.authors().await.unwrap();
+    assert_eq!(authors.iter().map(|a| (a.name.as_str(), a.quote_count)).collect::<Vec<_>>(), [("Dasher", 0), ("Rudolph", 2), ("Santa", 0)]);
+
+    // any Unicode whitespace separates words, only ASCII letters are case folded
+    let d = store.create(&ctx("test"), "\u{3000}Mrs\u{a0}Claus ".to_owned(), "Milk".to_owned(), Vec::new()).await.unwrap();
+    let e = store.create(&ctx("test"), "mrs \u{2003} CLAUS".to_owned(), "Cookies".to_owned(), Vec::new()).await.unwrap();
+    let f = store.create(&ctx("test"), "Élan".to_owned(), "Dash".to_owned(), Vec::new()).await.unwrap();
+    let g = store.create(&ctx("test"), "élan".to_owned(), "Dash away".to_owned(), Vec::new()).await.unwrap();
+    assert_eq!(d.author_id, e.author_id);
+    assert_ne!(f.author_id, g.author_id);
+    let by_author = QuoteFilter { author: Some("MRS\u{a0}claus".to_owned()), ..QuoteFilter::default() };
+    assert_eq!(store.list(0, 10, &by_author).await.unwrap().iter().map(|q| q.id).collect::<Vec<_>>(), [d.id, e.id]);
+    let authors = store.authors().await.unwrap();
+    assert!(authors.iter().any(|a| a.name == "Mrs\u{a0}Claus" && a.quote_count == 2), "{authors:?}");
+
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+async fn trash<S: QuoteStore>(store: &S) {
+    let a = store.create(&ctx("test"), "Elf".to_owned(), "One".to_owned(), strings(&["toy"])).await.unwrap();
+    let b = store.create(&ctx("test"), "Elf".to_owned(), "Two".to_owned(), Vec::new()).await.unwrap();
+    store.delete(&ctx("test"), a.id).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    store.delete(&ctx("test"), b.id).await.unwrap();
+
+    let trash = store.trash().await.unwrap();
+    assert_eq!(trash.iter().map(|q| q.id).collect::<Vec<_>>(), [b.id, a.id]);
+    assert!(trash.iter().all(|q| q.deleted_at.is_some()));
+
+    let restored = store.restore(a.id).await.unwrap();
+    assert!(restored.deleted_at.is_none());
+    assert_eq!(restored.tags, strings(&["toy"]));
+    assert_eq!(store.get(a.id).await.unwrap().version, a.version);
+    assert!(matches!(store.restore(a.id).await, Err(StoreError::NotFound)));
+
+    // a long retention keeps the trash, none purges it
+    assert_eq!(store.purge_trash(Duration::from_secs(60 * 60)).await.unwrap(), 0);
+    assert_eq!(store.purge_trash(Duration::ZERO).await.unwrap(), 1);
+    assert!(store.trash().await.unwrap().is_empty());
+    assert!(matches!(store.restore(b.id).await, Err(StoreError::NotFound)));
+    assert_eq!(store.get(a.id).await.unwrap().id, a.id);
+
+    // a soft clear moves everything to the trash
+    store.clear(&ctx("test"), false).await.unwrap();
+    assert_eq!(store.trash().await.unwrap().len(), 1);
+    assert_eq!(store.count(&QuoteFilter::default()).await.unwrap(), 0);
+
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+async fn search<S: QuoteStore>(store: &S) {
+    let a = store.create(&ctx("test"), "Santa".to_owned(), "<b>Reindeer</b> & sleigh".to_owned(), strings(&["ride"])).await.unwrap();
+    let b = store.create(&ctx("test"), "Mrs Claus".to_owned(), "Cookies for the reindeer".to_owned(), Vec::new()).await.unwrap();
+    let c = store.create(&ctx("test"), "Grinch".to_owned(), "Bah".to_owned(), Vec::new()).await.unwrap();
+    store.delete(&ctx("test"), c.id).await.unwrap();
+
+    let query = |text: &str| SearchQuery { text: text.to_owned(), ..SearchQuery::default() };
+
+    let mut hits: Vec<_> = store.search(&query("reindeer"), 0, 10).await.unwrap()
+        .into_iter()
+        .map(|h| h.quote.id)
+        .collect();
+    hits.sort();
+    let mut expected = vec![a.id, b.id];
+    expected.sort();
+    assert_eq!(hits, expected);
+
+    // every word must match, in the author or the quote
+    let hits = store.search(&query("reindeer santa"), 0, 10).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].quote.id, a.id);
+    assert_eq!(hits[0].quote.tags, strings(&["ride"]));
+    assert!(hits[0].rank > 0.0);
+
+    // snippets keep the quote text as is, matches between the highlight markers
+    assert!(hits[0].snippet.contains(&format!("<b>{HIGHLIGHT_START}Reindeer{HIGHLIGHT_END}</b> & sleigh")), "{:?}", hits[0].snippet);
+
+    assert!(store.search(&query("bah"), 0, 10).await.unwrap().is_empty());
+    assert_eq!(store.search(&query("reindeer"), 1, 10).await.unwrap().len(), 1);
+
+    let by_author = SearchQuery { author: Some("claus".to_owned()), ..query("reindeer") };
+    assert_eq!(store.search(&by_author, 0, 10).await.unwrap().iter().map(|h| h.quote.id).collect::<Vec<_>>(), [b.id]);
+    let by_tag = SearchQuery { tags: TagFilter::new(strings(&["ride"]), true), ..query("reindeer") };
+    assert_eq!(store.search(&by_tag, 0, 10).await.unwrap().iter().map(|h| h.quote.id).collect::<Vec<_>>(), [a.id]);
+
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+fn rows(quotes: Vec<ImportedQuote>) -> impl Stream<Item = Result<ImportedQuote, StoreError>> + Send {
+    futures::stream::iter(quotes.into_iter().map(Ok))
+}
+
+async fn import_export<S: QuoteStore>(store: &S) {
+    let existing = store.create(&ctx("test"), "Elf".to_owned(), "Here first".to_owned(), Vec::new()).await.unwrap();
+    let imported = |id: Uuid, quote: &str, version: i32| ImportedQuote {
+        id,
+        author: "Frosty".to_owned(),
+        quote: quote.to_owned(),
+        created_at: Local::now(),
+        version,
+    };
+    let new_id = Uuid::new_v4();
+    let quotes = vec![
+        imported(new_id, "Snow", 3),
+        imported(existing.id, "Taken", 1),
+        imported(new_id, "Twice", 1),
+    ];
+
+    assert_eq!(store.import(rows(quotes.clone()), true).await.unwrap(), 1);
+    assert!(matches!(store.get(new_id).await, Err(StoreError::NotFound)));
+    assert!(store.authors().await.unwrap().iter().all(|a| a.name != "Frosty"));
+
+    // an error anywhere in the stream drops every row
+    let aborted = rows(quotes.clone()).chain(futures::stream::once(async { Err(StoreError::Aborted) }));
+    assert!(matches!(store.import(aborted, false).await, Err(StoreError::Aborted)));
+    assert!(matches!(store.get(new_id).await, Err(StoreError::NotFound)));
+
+    assert_eq!(store.import(rows(quotes.clone()), false).await.unwrap(), 1);
+    let q = store.get(new_id).await.unwrap();
+    assert_eq!((q.author.as_str(), q.quote.as_str(), q.version), ("Frosty", "Snow", 3));
+    assert!(q.author_id.is_some());
+    assert_eq!(store.get(existing.id).await.unwrap().quote, "Here first");
+    assert_eq!(store.import(rows(quotes), false).await.unwrap(), 0);
+
+    // ids in the trash are taken too
+    store.delete(&ctx("test"), existing.id).await.unwrap();
+    assert_eq!(store.import(rows(vec![imported(existing.id, "Again", 1)]), false).await.unwrap(), 0);
+
+    let exported: Vec<_> = store.export().try_collect().await.unwrap();
+    assert_eq!(exported.iter().map(|q| q.id).collect::<Vec<_>>(), [new_id]);
+
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+async fn changes<S: QuoteStore>(store: &S) {
+    let last = store.changes_since(0).await.unwrap().last().map_or(0, |c| c.seq);
+    let mut rx = store.subscribe();
+
+    let q = store.create(&ctx("test"), "Elf".to_owned(), "Change".to_owned(), Vec::new()).await.unwrap();
+    store.update(&ctx("test"), q.id, None, "Elf".to_owned(), "Changed".to_owned(), None).await.unwrap();
+    store.delete(&ctx("test"), q.id).await.unwrap();
+    store.restore(q.id).await.unwrap();
+    store.clear(&ctx("test"), true).await.unwrap();
+
+    let changes = store.changes_since(last).await.unwrap();
+    assert_eq!(ops(&changes, q.id), ["draft", "undo", "remove", "restore", "purge"]);
+    assert!(changes.windows(2).all(|w| w[0].seq < w[1].seq));
+    assert_eq!(changes.iter().map(|c| c.version).collect::<Vec<_>>(), [1, 2, 2, 2, 2]);
+    assert_eq!(store.last_change_seq().await.unwrap(), changes.last().unwrap().seq);
+    let after_first = store.changes_since(changes[0].seq).await.unwrap();
+    assert_eq!(after_first.len(), changes.len() - 1);
+
+    let mut live = Vec::new();
+    while live.len() < changes.len() {
+        let change = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await
+            .expect("change not sent to subscribers")
+            .unwrap();
+        // notifications of the previous steps may still be on their way
+        if change.seq > last {
+            live.push(change);
+        };
+    };
+    assert_eq!(live.iter().map(|c| c.seq).collect::<Vec<_>>(), changes.iter().map(|c| c.seq).collect::<Vec<_>>());
+
+    assert_eq!(store.purge_changes(Duration::from_secs(60 * 60)).await.unwrap(), 0);
+    assert!(store.purge_changes(Duration::ZERO).await.unwrap() >= changes.len() as u64);
+    assert!(store.changes_since(0).await.unwrap().is_empty());
+
+    // seqs keep increasing after a purge
+    let q = store.create(&ctx("test"), "Elf".to_owned(), "Again".to_owned(), Vec::new()).await.unwrap();
+    let after = store.changes_since(0).await.unwrap();
+    assert_eq!(ops(&after, q.id), ["draft"]);
+    assert!(after[0].seq > changes.last().unwrap().seq);
+    assert_eq!(store.last_change_seq().await.unwrap(), after[0].seq);
+    store.clear(&ctx("test"), true).await.unwrap();
+}
+
+async fn clear<S: QuoteStore>(store: &S) {
+    store.create(&ctx("test"), "Elf".to_owned(), "One".to_owned(), Vec::new()).await.unwrap();
+    store.create(&ctx("test"), "Elf".to_owned(), "Two".to_owned(), Vec::new()).await.unwrap();
+
+    store.clear(&ctx("janitor"), false).await.unwrap();
+    assert_eq!(store.count(&QuoteFilter::default()).await.unwrap(), 0);
+    assert_eq!(store.trash().await.unwrap().len(), 2);
+
+    store.clear(&ctx("janitor"), true).await.unwrap();
+    assert!(store.trash().await.unwrap().is_empty());
+
+    let filter = AuditFilter { actor: Some("janitor".to_owned()), ..AuditFilter::default() };
+    let log = store.audit_log(&filter, 0, 10).await.unwrap();
+    assert_eq!(log.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), ["clear", "clear"]);
+    assert!(log.iter().all(|e| e.quote_id.is_none()));
+    assert_eq!(log[0].after.as_ref().unwrap()["hard"], true);
+    assert_eq!(log[1].after.as_ref().unwrap()["quotes"], 2);
+}
+
+#[tokio::test]
+async fn memory() {
+    conformance(MemoryQuoteStore::new()).await;
+}
+
+/// Needs a Postgres server at `DATABASE_URL`, run with `cargo test -- --ignored`
+#[sqlx::test]
+#[ignore = "needs DATABASE_URL"]
+async fn postgres(pool: PgPool) {
+    let store = PgQuoteStore::new(Arc::new(pool));
+    store.listen().await.unwrap();
+    conformance(store).await;
+}
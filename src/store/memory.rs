@@ -0,0 +1,437 @@
+use std::{collections::{HashMap, HashSet}, sync::Mutex, time::Duration};
+
+use chrono::SubsecRound;
+use futures::{Stream, TryStreamExt};
+use sqlx::types::{chrono::{DateTime, Local}, Uuid};
+use tokio::sync::broadcast;
+
+use serde_json::{json, Value};
+
+use crate::models::{AuditContext, AuditEntry, Author, Quote, QuoteChange, QuoteHit, Tag};
+use super::{normalize_author, normalize_tags, AuditFilter, ImportedQuote, HIGHLIGHT_END, HIGHLIGHT_START, QuoteFilter, QuoteStore, SearchQuery, StoreError};
+
+/// Current time at the microsecond precision of `TIMESTAMPTZ`
+fn now() -> DateTime<Local> {
+    Local::now().trunc_subsecs(6)
+}
+
+#[derive(Default)]
+struct Inner {
+    quotes: HashMap<Uuid, Quote>,
+    /// Author ID and display name by normalized name
+    authors: HashMap<String, (Uuid, String)>,
+    audit: Vec<AuditEntry>,
+    changes: Vec<QuoteChange>,
+    /// Last `seq` given out, the change log may be purged
+    last_seq: i64,
+}
+
+impl Inner {
+    /// Find the author of `name`, creating them on first sight
+    fn resolve_author(&mut self, name: &str) -> Uuid {
+        self.authors.entry(normalize_author(name))
+            .or_insert_with(|| (Uuid::new_v4(), name.trim().to_owned()))
+            .0
+    }
+
+    fn record(&mut self, ctx: &AuditContext, action: &str, quote_id: Option<Uuid>, before: Option<Value>, after: Option<Value>) {
+        let entry = AuditEntry {
+            id: self.audit.len() as i64 + 1,
+            quote_id,
+            action: action.to_owned(),
+            actor: ctx.actor.clone(),
+            request_id: ctx.request_id.clone(),
+            ip: ctx.ip.clone(),
+            before,
+            after,
+            at: now(),
+        };
+        self.audit.push(entry);
+    }
+
+    /// Append to the change log, return the change to send to subscribers
+    fn change(&mut self, quote: &Quote, op: &str) -> QuoteChange {
+        self.last_seq += 1;
+        let change = QuoteChange {
+            seq: self.last_seq,
+            quote_id: quote.id,
+            op: op.to_owned(),
+            version: quote.version,
+            changed_at: now(),
+        };
+        self.changes.push(change.clone());
+        change
+    }
+
+    /// Live quotes ordered like `list`
+    fn live_quotes(&self, filter: impl Fn(&Quote) -> bool) -> Vec<&Quote> {
+        let mut live: Vec<&Quote> = self.quotes.values()
+            .filter(|q| q.deleted_at.is_none() && filter(q))
+            .collect();
+        live.sort_by_key(|q| (q.created_at, q.id));
+        live
+    }
+}
+
+fn page(quotes: Vec<&Quote>, offset: u64, limit: u64) -> Vec<Quote> {
+    quotes.into_iter()
+        .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+        .take(usize::try_from(limit).unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
+
+/// Lowercase words of `text`, the unit of search
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// `text` with the words of `terms` between highlight markers
+fn highlight(text: &str, terms: &HashSet<String>) -> String {
+    let mut snippet = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let in_word = rest.starts_with(char::is_alphanumeric);
+        let end = rest.find(|c: char| c.is_alphanumeric() != in_word).unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(end);
+        if in_word && terms.contains(&part.to_lowercase()) {
+            snippet.push(HIGHLIGHT_START);
+            snippet.push_str(part);
+            snippet.push(HIGHLIGHT_END);
+        } else {
+            snippet.push_str(part);
+        };
+        rest = tail;
+    };
+    snippet
+}
+
+/// Quotes kept in process memory, with the same semantics as `PgQuoteStore`
+///
+/// Search matches whole words only, without stemming.
+pub struct MemoryQuoteStore {
+    inner: Mutex<Inner>,
+    changes: broadcast::Sender<QuoteChange>,
+}
+
+impl Default for MemoryQuoteStore {
+    fn default() -> Self {
+        let (changes, _) = broadcast::channel(256);
+        Self {
+            inner: Mutex::default(),
+            changes,
+        }
+    }
+}
+
+impl MemoryQuoteStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send changes made under the lock, nobody listening is fine
+    fn send(&self, changes: Vec<QuoteChange>) {
+        for change in changes {
+            let _ = self.changes.send(change);
+        };
+    }
+}
+
+impl QuoteStore for MemoryQuoteStore {
+    async fn create(&self, ctx: &AuditContext, author: String, quote: String, tags: Vec<String>) -> Result<Quote, StoreError> {
+        let mut inner = self.inner.lock().unwrap();
+        let q = Quote {
+            id: Uuid::new_v4(),
+            author_id: Some(inner.resolve_author(&author)),
+            author,
+            quote,
+            created_at: now(),
+            version: 1,
+            tags: normalize_tags(tags),
+            deleted_at: None,
+        };
+
+        inner.quotes.insert(q.id, q.clone());
+        inner.record(ctx, "draft", Some(q.id), None, Some(json!(q)));
+        let change = inner.change(&q, "draft");
+        self.send(vec![change]);
+        Ok(q)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Quote, StoreError> {
+        self.inner.lock().unwrap()
+            .quotes
+            .get(&id)
+            .filter(|q| q.deleted_at.is_none())
+            .cloned()
+            .ok_or(StoreError::NotFound)
+    }
+
+    async fn update(&self, ctx: &AuditContext, id: Uuid, expected_version: Option<i32>, author: String, quote: String, tags: Option<Vec<String>>) -> Result<Quote, StoreError> {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.quotes.get(&id)
+            .filter(|q| q.deleted_at.is_none())
+            .ok_or(StoreError::NotFound)?;
+        if expected_version.is_some_and(|v| v != before.version) {
+            return Err(StoreError::Conflict);
+        };
+        let before = json!(before);
+        let author_id = inner.resolve_author(&author);
+        let q = inner.quotes.get_mut(&id).unwrap();
+
+        q.author = author;
+        q.author_id = Some(author_id);
+        q.quote = quote;
+        if let Some(tags) = tags {
+            q.tags = normalize_tags(tags);
+        };
+        q.version += 1;
+        let q = q.clone();
+        inner.record(ctx, "undo", Some(id), Some(before), Some(json!(q)));
+        let change = inner.change(&q, "undo");
+        self.send(vec![change]);
+        Ok(q)
+    }
+
+    async fn delete(&self, ctx: &AuditContext, id: Uuid) -> Result<Quote, StoreError> {
+        let mut inner = self.inner.lock().unwrap();
+        let q = inner.quotes.get_mut(&id)
+            .filter(|q| q.deleted_at.is_none())
+            .ok_or(StoreError::NotFound)?;
+        let before = json!(q);
+
+        q.deleted_at = Some(now());
+        let q = q.clone();
+        inner.record(ctx, "remove", Some(id), Some(before), Some(json!(q)));
+        let change = inner.change(&q, "remove");
+        self.send(vec![change]);
+        Ok(q)
+    }
+
+    async fn clear(&self, ctx: &AuditContext, hard: bool) -> Result<(), StoreError> {
+        let mut inner = self.inner.lock().unwrap();
+        let cleared: Vec<Quote> = if hard {
+            inner.quotes.drain().map(|(_, q)| q).collect()
+        } else {
+            let now = now();
+            inner.quotes.values_mut()
+                .filter(|q| q.deleted_at.is_none())
+                .map(|q| {
+                    q.deleted_at = Some(now);
+                    q.clone()
+                })
+                .collect()
+        };
+        let op = if hard { "purge" } else { "remove" };
+        let changes = cleared.iter().map(|q| inner.change(q, op)).collect();
+        inner.record(ctx, "clear", None, None, Some(json!({ "hard": hard, "quotes": cleared.len() })));
+        self.send(changes);
+        Ok(())
+    }
+
+    async fn list(&self, offset: u64, limit: u64, filter: &QuoteFilter) -> Result<Vec<Quote>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        Ok(page(inner.live_quotes(|q| filter.matches(q)), offset, limit))
+    }
+
+    async fn count(&self, filter: &QuoteFilter) -> Result<u64, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.live_quotes(|q| filter.matches(q)).len() as u64)
+    }
+
+    async fn authors(&self) -> Result<Vec<Author>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        let mut authors: Vec<Author> = inner.authors.values()
+            .map(|(id, name)| {
+                let quotes = inner.live_quotes(|q| q.author_id == Some(*id));
+                Author {
+                    id: *id,
+                    name: name.clone(),
+                    quote_count: quotes.len() as i64,
+                    latest_quote_at: quotes.iter().map(|q| q.created_at).max(),
+                }
+            })
+            .collect();
+        authors.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+        Ok(authors)
+    }
+
+    async fn author_quotes(&self, author_id: Uuid, offset: u64, limit: u64) -> Result<Vec<Quote>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.authors.values().any(|(id, _)| *id == author_id) {
+            return Err(StoreError::NotFound);
+        };
+
+        Ok(page(inner.live_quotes(|q| q.author_id == Some(author_id)), offset, limit))
+    }
+
+    async fn tags(&self) -> Result<Vec<Tag>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        let mut counts: HashMap<&str, i64> = HashMap::new();
+        for q in inner.live_quotes(|_| true) {
+            for t in &q.tags {
+                *counts.entry(t.as_str()).or_default() += 1;
+            };
+        };
+
+        let mut tags: Vec<Tag> = counts.into_iter()
+            .map(|(name, quote_count)| Tag { name: name.to_owned(), quote_count })
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter, offset: u64, limit: u64) -> Result<Vec<AuditEntry>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.audit.iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    async fn search(&self, query: &SearchQuery, offset: u64, limit: u64) -> Result<Vec<QuoteHit>, StoreError> {
+        let terms: HashSet<String> = words(&query.text).collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        };
+        let author = query.author.as_ref().map(|a| a.to_lowercase());
+
+        let inner = self.inner.lock().unwrap();
+        let mut hits: Vec<QuoteHit> = inner.live_quotes(|q| {
+            author.as_ref().is_none_or(|a| q.author.to_lowercase().contains(a.as_str()))
+                && query.tags.matches(&q.tags)
+        })
+            .into_iter()
+            .filter_map(|q| {
+                let found: Vec<String> = words(&q.author).chain(words(&q.quote)).collect();
+                if !terms.iter().all(|t| found.contains(t)) {
+                    return None;
+                };
+                let matched = found.iter().filter(|w| terms.contains(*w)).count();
+                Some(QuoteHit {
+                    quote: q.clone(),
+                    rank: matched as f32 / found.len() as f32,
+                    snippet: highlight(&q.quote, &terms),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank).then(b.quote.created_at.cmp(&a.quote.created_at)));
+
+        Ok(hits.into_iter()
+            .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    async fn import<Q>(&self, quotes: Q, dry_run: bool) -> Result<u64, StoreError>
+    where
+        Q: Stream<Item = Result<ImportedQuote, StoreError>> + Send,
+    {
+        let quotes: Vec<ImportedQuote> = quotes.try_collect().await?;
+        let mut inner = self.inner.lock().unwrap();
+        let mut seen = HashSet::new();
+        let new: Vec<ImportedQuote> = quotes.into_iter()
+            .filter(|q| !inner.quotes.contains_key(&q.id) && seen.insert(q.id))
+            .collect();
+        let inserted = new.len() as u64;
+        if dry_run {
+            return Ok(inserted);
+        };
+
+        let mut changes = Vec::with_capacity(new.len());
+        for row in new {
+            let q = Quote {
+                id: row.id,
+                author_id: Some(inner.resolve_author(&row.author)),
+                author: row.author,
+                quote: row.quote,
+                created_at: row.created_at.trunc_subsecs(6),
+                version: row.version,
+                tags: Vec::new(),
+                deleted_at: None,
+            };
+            changes.push(inner.change(&q, "draft"));
+            inner.quotes.insert(q.id, q);
+        };
+        self.send(changes);
+        Ok(inserted)
+    }
+
+    fn export(&self) -> impl Stream<Item = Result<Quote, StoreError>> + Send + 'static {
+        let quotes: Vec<Quote> = self.inner.lock().unwrap()
+            .live_quotes(|_| true)
+            .into_iter()
+            .cloned()
+            .collect();
+        futures::stream::iter(quotes.into_iter().map(Ok))
+    }
+
+    async fn trash(&self) -> Result<Vec<Quote>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        let mut trash: Vec<Quote> = inner.quotes.values()
+            .filter(|q| q.deleted_at.is_some())
+            .cloned()
+            .collect();
+        trash.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(a.id.cmp(&b.id)));
+        Ok(trash)
+    }
+
+    async fn restore(&self, id: Uuid) -> Result<Quote, StoreError> {
+        let mut inner = self.inner.lock().unwrap();
+        let q = inner.quotes.get_mut(&id)
+            .filter(|q| q.deleted_at.is_some())
+            .ok_or(StoreError::NotFound)?;
+
+        q.deleted_at = None;
+        let q = q.clone();
+        let change = inner.change(&q, "restore");
+        self.send(vec![change]);
+        Ok(q)
+    }
+
+    async fn purge_trash(&self, retention: Duration) -> Result<u64, StoreError> {
+        let cutoff = Local::now() - retention;
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<Uuid> = inner.quotes.values()
+            .filter(|q| q.deleted_at.is_some_and(|at| at < cutoff))
+            .map(|q| q.id)
+            .collect();
+
+        let mut changes = Vec::with_capacity(expired.len());
+        for id in &expired {
+            let q = inner.quotes.remove(id).unwrap();
+            changes.push(inner.change(&q, "purge"));
+        };
+        self.send(changes);
+        Ok(expired.len() as u64)
+    }
+
+    async fn purge_changes(&self, retention: Duration) -> Result<u64, StoreError> {
+        let cutoff = Local::now() - retention;
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.changes.len();
+        inner.changes.retain(|c| c.changed_at >= cutoff);
+        Ok((before - inner.changes.len()) as u64)
+    }
+
+    async fn changes_since(&self, seq: i64) -> Result<Vec<QuoteChange>, StoreError> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.changes.iter()
+            .filter(|c| c.seq > seq)
+            .cloned()
+            .collect())
+    }
+
+    async fn last_change_seq(&self) -> Result<i64, StoreError> {
+        Ok(self.inner.lock().unwrap().last_seq)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<QuoteChange> {
+        self.changes.subscribe()
+    }
+}
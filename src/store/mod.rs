@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod conformance;
+mod memory;
+mod pg;
+
+use std::{error::Error, fmt, future::Future, time::Duration};
+
+use futures::Stream;
+use sqlx::types::{chrono::{DateTime, Local}, Uuid};
+use tokio::sync::broadcast;
+
+use crate::models::{AuditContext, AuditEntry, Author, Quote, QuoteChange, QuoteHit, Tag};
+
+pub use memory::MemoryQuoteStore;
+pub use pg::PgQuoteStore;
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// No live quote (or author) with the given ID
+    NotFound,
+    /// The quote is not at the expected version
+    Conflict,
+    /// The caller gave up halfway, e.g. on an invalid import row
+    Aborted,
+    Backend(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound => write!(f, "quote not found"),
+            StoreError::Conflict => write!(f, "quote changed meanwhile"),
+            StoreError::Aborted => write!(f, "aborted by the caller"),
+            StoreError::Backend(e) => write!(f, "storage backend error: {}", e),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+/// Trim, lowercase, sort and deduplicate tag names, dropping empty ones
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags.into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Restrict a listing to quotes carrying all (AND) or any (OR) of some tags
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    /// Normalized tag names, no restriction if empty
+    pub tags: Vec<String>,
+    pub all: bool,
+}
+
+impl TagFilter {
+    pub fn new(tags: Vec<String>, all: bool) -> Self {
+        Self {
+            tags: normalize_tags(tags),
+            all,
+        }
+    }
+
+    pub fn matches(&self, quote_tags: &[String]) -> bool {
+        if self.tags.is_empty() {
+            true
+        } else if self.all {
+            self.tags.iter().all(|t| quote_tags.contains(t))
+        } else {
+            self.tags.iter().any(|t| quote_tags.contains(t))
+        }
+    }
+}
+
+/// Same normalization as the `normalize_author` SQL function
+///
+/// Runs of Unicode whitespace become one space and only ASCII letters are case folded,
+/// the database cannot fold the others the same whatever its locale.
+pub fn normalize_author(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_lowercase()
+}
+
+/// Restrict a listing by author and tags
+#[derive(Debug, Clone, Default)]
+pub struct QuoteFilter {
+    /// Author name, matched ignoring case and whitespace
+    pub author: Option<String>,
+    pub tags: TagFilter,
+}
+
+impl QuoteFilter {
+    pub fn matches(&self, quote: &Quote) -> bool {
+        self.author.as_ref().is_none_or(|a| normalize_author(a) == normalize_author(&quote.author))
+            && self.tags.matches(&quote.tags)
+    }
+}
+
+/// Restrict the audit log by quote, actor and time range (`from` inclusive, `to` exclusive)
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub quote_id: Option<Uuid>,
+    pub actor: Option<String>,
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.quote_id.is_none_or(|id| entry.quote_id == Some(id))
+            && self.actor.as_ref().is_none_or(|a| *a == entry.actor)
+            && self.from.is_none_or(|from| entry.at >= from)
+            && self.to.is_none_or(|to| entry.at < to)
+    }
+}
+
+/// Full-text search over live quotes
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// Words that must all appear in the author or the quote
+    pub text: String,
+    /// Part of the author name, ignoring case
+    pub author: Option<String>,
+    pub tags: TagFilter,
+}
+
+/// A validated quote of an import, inserted as is
+#[derive(Debug, Clone)]
+pub struct ImportedQuote {
+    pub id: Uuid,
+    pub author: String,
+    pub quote: String,
+    pub created_at: DateTime<Local>,
+    pub version: i32,
+}
+
+/// Start of a match in a search snippet, a control character so never part of a quote
+pub const HIGHLIGHT_START: char = '\u{2}';
+/// End of a match in a search snippet
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+/// Storage of quotes
+///
+/// # Contract
+///
+/// - new quotes start at `version` 1, every update bumps it by one
+/// - `created_at` is set on creation and never changes
+/// - deleted quotes go to the trash, they are invisible to everything but `clear`
+/// - `list` orders by `created_at`, then `id`
+/// - quotes are linked to an author with the same `normalize_author` name,
+///   the first spelling seen is the author's display name
+/// - tags are normalized with `normalize_tags`, `update` keeps them unless given
+/// - `create`, `update`, `delete` and `clear` append to the audit log along with the change,
+///   as actions `draft`, `undo`, `remove` and `clear`
+/// - every change to a quote is recorded in the change log with an increasing `seq` and sent
+///   to subscribers, as ops `draft`, `undo`, `remove`, `restore` or `purge` (gone for good)
+/// - `search` snippets are the raw quote text, with matches between `HIGHLIGHT_START` and `HIGHLIGHT_END`
+pub trait QuoteStore: Send + Sync + 'static {
+    fn create(&self, ctx: &AuditContext, author: String, quote: String, tags: Vec<String>) -> impl Future<Output = Result<Quote, StoreError>> + Send;
+
+    fn get(&self, id: Uuid) -> impl Future<Output = Result<Quote, StoreError>> + Send;
+
+    /// Replace a quote, only if it is still at `expected_version` when given
+    fn update(&self, ctx: &AuditContext, id: Uuid, expected_version: Option<i32>, author: String, quote: String, tags: Option<Vec<String>>) -> impl Future<Output = Result<Quote, StoreError>> + Send;
+
+    /// Move a quote to the trash, return it as it was deleted
+    fn delete(&self, ctx: &AuditContext, id: Uuid) -> impl Future<Output = Result<Quote, StoreError>> + Send;
+
+    /// Move every quote to the trash, or remove everything for good if `hard`
+    fn clear(&self, ctx: &AuditContext, hard: bool) -> impl Future<Output = Result<(), StoreError>> + Send;
+
+    fn list(&self, offset: u64, limit: u64, filter: &QuoteFilter) -> impl Future<Output = Result<Vec<Quote>, StoreError>> + Send;
+
+    /// Number of live quotes `list` would go through
+    fn count(&self, filter: &QuoteFilter) -> impl Future<Output = Result<u64, StoreError>> + Send;
+
+    /// Every author ordered by display name, aggregated over live quotes
+    fn authors(&self) -> impl Future<Output = Result<Vec<Author>, StoreError>> + Send;
+
+    /// Live quotes of an author, ordered like `list`
+    fn author_quotes(&self, author_id: Uuid, offset: u64, limit: u64) -> impl Future<Output = Result<Vec<Quote>, StoreError>> + Send;
+
+    /// Tags in use by live quotes, ordered by name
+    fn tags(&self) -> impl Future<Output = Result<Vec<Tag>, StoreError>> + Send;
+
+    /// Audit log entries, newest first
+    fn audit_log(&self, filter: &AuditFilter, offset: u64, limit: u64) -> impl Future<Output = Result<Vec<AuditEntry>, StoreError>> + Send;
+
+    /// Live quotes matching `query`, most relevant first
+    fn search(&self, query: &SearchQuery, offset: u64, limit: u64) -> impl Future<Output = Result<Vec<QuoteHit>, StoreError>> + Send;
+
+    /// Insert the quotes of `quotes` whose `id` is not taken yet as they come, all or nothing,
+    /// return how many were inserted; nothing is kept if `dry_run`,
+    /// or if `quotes` yields an error, which is returned
+    fn import<Q>(&self, quotes: Q, dry_run: bool) -> impl Future<Output = Result<u64, StoreError>> + Send
+    where
+        Q: Stream<Item = Result<ImportedQuote, StoreError>> + Send;
+
+    /// Every live quote, ordered like `list`
+    fn export(&self) -> impl Stream<Item = Result<Quote, StoreError>> + Send + 'static;
+
+    /// Quotes in the trash, most recently deleted first
+    fn trash(&self) -> impl Future<Output = Result<Vec<Quote>, StoreError>> + Send;
+
+    /// Take a quote out of the trash
+    fn restore(&self, id: Uuid) -> impl Future<Output = Result<Quote, StoreError>> + Send;
+
+    /// Remove quotes that have been in the trash for longer than `retention`, return how many
+    fn purge_trash(&self, retention: Duration) -> impl Future<Output = Result<u64, StoreError>> + Send;
+
+    /// Drop changes older than `retention` from the change log, return how many
+    fn purge_changes(&self, retention: Duration) -> impl Future<Output = Result<u64, StoreError>> + Send;
+
+    /// Changes after `seq`, oldest first
+    fn changes_since(&self, seq: i64) -> impl Future<Output = Result<Vec<QuoteChange>, StoreError>> + Send;
+
+    /// `seq` of the latest change, where `changes_since` picks up from now, 0 if there are none
+    fn last_change_seq(&self) -> impl Future<Output = Result<i64, StoreError>> + Send;
+
+    /// Changes as they happen, from now on
+    fn subscribe(&self) -> broadcast::Receiver<QuoteChange>;
+}
@@ -0,0 +1,431 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{Stream, TryStreamExt};
+use serde_json::{json, Value};
+use sqlx::{postgres::PgListener, types::{Json, Uuid}, PgPool, Postgres, Transaction};
+use tokio::sync::broadcast;
+
+use crate::models::{AuditContext, AuditEntry, Author, Quote, QuoteChange, QuoteHit, Tag};
+use super::{normalize_tags, AuditFilter, ImportedQuote, HIGHLIGHT_END, HIGHLIGHT_START, QuoteFilter, QuoteStore, SearchQuery, StoreError};
+
+const IMPORT_BATCH_SIZE: usize = 500;
+
+impl From<sqlx::Error> for StoreError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => StoreError::NotFound,
+            other => StoreError::Backend(Box::new(other)),
+        }
+    }
+}
+
+/// Sorted tag names of the quote in the current row of `quotes`
+const QUOTE_TAGS_SQL: &str = "ARRAY(SELECT tags.name FROM quote_tags JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id ORDER BY tags.name)";
+
+/// Condition on the current row of `quotes` implementing `TagFilter`,
+/// given the positions of its `tags` (`TEXT[]`) and `all` (`BOOL`) parameters
+fn tag_filter_sql(tags_param: usize, all_param: usize) -> String {
+    format!(r#"(
+        cardinality(${tags}::TEXT[]) = 0 OR (
+            SELECT CASE WHEN ${all}::BOOL
+                THEN COUNT(*) = cardinality(${tags}::TEXT[])
+                ELSE COUNT(*) > 0
+            END
+            FROM quote_tags JOIN tags ON tags.id = quote_tags.tag_id
+            WHERE quote_tags.quote_id = quotes.id AND tags.name = ANY(${tags}::TEXT[])
+        )
+    )"#, tags = tags_param, all = all_param)
+}
+
+/// Condition on the current row of `quotes` implementing `QuoteFilter`,
+/// given the position of its `author` (`TEXT`) parameter followed by those of `tag_filter_sql`
+fn quote_filter_sql(author_param: usize) -> String {
+    format!(
+        "(${a}::TEXT IS NULL OR quotes.author_id = (SELECT id FROM authors WHERE normalized_name = normalize_author(${a}))) AND {}",
+        tag_filter_sql(author_param + 1, author_param + 2),
+        a = author_param,
+    )
+}
+
+/// Quotes in the Postgres `quotes` table
+pub struct PgQuoteStore {
+    pool: Arc<PgPool>,
+    changes: broadcast::Sender<QuoteChange>,
+}
+
+impl PgQuoteStore {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        let (changes, _) = broadcast::channel(256);
+        Self { pool, changes }
+    }
+
+    /// Relay `quote_changes` notifications to subscribers, until then they only get the change log
+    pub async fn listen(&self) -> Result<(), sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen("quote_changes").await?;
+
+        let tx = self.changes.clone();
+        tokio::spawn(async move {
+            loop {
+                // `recv` reconnects by itself, notifications sent meanwhile are lost
+                // but subscribers can catch up with `changes_since`
+                match listener.recv().await {
+                    Ok(n) => match serde_json::from_str::<QuoteChange>(n.payload()) {
+                        Ok(change) => { let _ = tx.send(change); },
+                        Err(e) => tracing::warn!(error = %e, "malformed quote change notification"),
+                    },
+                    Err(e) => {
+                        tracing::error!(error = %e, "quote change listener error");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    },
+                };
+            };
+        });
+
+        Ok(())
+    }
+
+    /// Insert a batch of imported quotes, return the number of inserted rows
+    async fn insert_batch(tx: &mut Transaction<'_, Postgres>, batch: &[ImportedQuote]) -> Result<u64, sqlx::Error> {
+        let res = sqlx::query(r#"
+            INSERT INTO quotes (id, author, quote, created_at, version)
+            SELECT * FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[], $4::TIMESTAMPTZ[], $5::INT[])
+            ON CONFLICT (id) DO NOTHING
+        "#)
+            .bind(batch.iter().map(|q| q.id).collect::<Vec<_>>())
+            .bind(batch.iter().map(|q| q.author.as_str()).collect::<Vec<_>>())
+            .bind(batch.iter().map(|q| q.quote.as_str()).collect::<Vec<_>>())
+            .bind(batch.iter().map(|q| q.created_at).collect::<Vec<_>>())
+            .bind(batch.iter().map(|q| q.version).collect::<Vec<_>>())
+            .execute(&mut **tx)
+            .await?;
+        Ok(res.rows_affected())
+    }
+
+    /// Replace the tags of a quote
+    async fn set_tags(tx: &mut Transaction<'_, Postgres>, id: Uuid, tags: Vec<String>) -> Result<(), sqlx::Error> {
+        let tags = normalize_tags(tags);
+
+        sqlx::query("INSERT INTO tags (name) SELECT UNNEST($1::TEXT[]) ON CONFLICT (name) DO NOTHING")
+            .bind(&tags)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("DELETE FROM quote_tags WHERE quote_id = $1")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("INSERT INTO quote_tags (quote_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2)")
+            .bind(id)
+            .bind(&tags)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch(tx: &mut Transaction<'_, Postgres>, id: Uuid) -> Result<Quote, sqlx::Error> {
+        sqlx::query_as::<_, Quote>(&format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await
+    }
+
+    /// Lock a live quote for the rest of the transaction, return it as it is
+    async fn fetch_for_update(tx: &mut Transaction<'_, Postgres>, id: Uuid) -> Result<Quote, sqlx::Error> {
+        sqlx::query_as::<_, Quote>(&format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE id = $1 AND deleted_at IS NULL FOR UPDATE"))
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await
+    }
+
+    async fn record(
+        tx: &mut Transaction<'_, Postgres>,
+        ctx: &AuditContext,
+        action: &str,
+        quote_id: Option<Uuid>,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> Result<(), sqlx::Error>
+    {
+        sqlx::query("INSERT INTO quote_audit (quote_id, action, actor, request_id, ip, before, after) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(quote_id)
+            .bind(action)
+            .bind(&ctx.actor)
+            .bind(&ctx.request_id)
+            .bind(&ctx.ip)
+            .bind(before.map(Json))
+            .bind(after.map(Json))
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
+
+impl QuoteStore for PgQuoteStore {
+    async fn create(&self, ctx: &AuditContext, author: String, quote: String, tags: Vec<String>) -> Result<Quote, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let id = Uuid::new_v4();
+
+        sqlx::query("INSERT INTO quotes (id, author, quote) VALUES ($1, $2, $3)")
+            .bind(id)
+            .bind(author)
+            .bind(quote)
+            .execute(&mut *tx)
+            .await?;
+        Self::set_tags(&mut tx, id, tags).await?;
+
+        let q = Self::fetch(&mut tx, id).await?;
+        Self::record(&mut tx, ctx, "draft", Some(id), None, Some(json!(q))).await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Quote, StoreError> {
+        Ok(sqlx::query_as::<_, Quote>(&format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE id = $1 AND deleted_at IS NULL"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await?)
+    }
+
+    async fn update(&self, ctx: &AuditContext, id: Uuid, expected_version: Option<i32>, author: String, quote: String, tags: Option<Vec<String>>) -> Result<Quote, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::fetch_for_update(&mut tx, id).await?;
+        if expected_version.is_some_and(|v| v != before.version) {
+            return Err(StoreError::Conflict);
+        };
+
+        sqlx::query("UPDATE quotes SET author = $1, quote = $2, version = version + 1 WHERE id = $3")
+            .bind(author)
+            .bind(quote)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(tags) = tags {
+            Self::set_tags(&mut tx, id, tags).await?;
+        };
+
+        let q = Self::fetch(&mut tx, id).await?;
+        Self::record(&mut tx, ctx, "undo", Some(id), Some(json!(before)), Some(json!(q))).await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+
+    async fn delete(&self, ctx: &AuditContext, id: Uuid) -> Result<Quote, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let before = Self::fetch_for_update(&mut tx, id).await?;
+
+        let q = sqlx::query_as::<_, Quote>(&format!("UPDATE quotes SET deleted_at = CURRENT_TIMESTAMP WHERE id = $1 RETURNING quotes.*, {QUOTE_TAGS_SQL} AS tags"))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+        Self::record(&mut tx, ctx, "remove", Some(id), Some(json!(before)), Some(json!(q))).await?;
+        tx.commit().await?;
+        Ok(q)
+    }
+
+    async fn clear(&self, ctx: &AuditContext, hard: bool) -> Result<(), StoreError> {
+        let sql = if hard {
+            "DELETE FROM quotes"
+        } else {
+            "UPDATE quotes SET deleted_at = CURRENT_TIMESTAMP WHERE deleted_at IS NULL"
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let count = sqlx::query(sql)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        Self::record(&mut tx, ctx, "clear", None, None, Some(json!({ "hard": hard, "quotes": count }))).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list(&self, offset: u64, limit: u64, filter: &QuoteFilter) -> Result<Vec<Quote>, StoreError> {
+        Ok(sqlx::query_as::<_, Quote>(&format!(
+            "SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE deleted_at IS NULL AND {} ORDER BY created_at, id LIMIT $1 OFFSET $2",
+            quote_filter_sql(3),
+        ))
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .bind(&filter.author)
+            .bind(&filter.tags.tags)
+            .bind(filter.tags.all)
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn count(&self, filter: &QuoteFilter) -> Result<u64, StoreError> {
+        let (count,): (i64,) = sqlx::query_as(&format!(
+            "SELECT COUNT(*) FROM quotes WHERE deleted_at IS NULL AND {}",
+            quote_filter_sql(1),
+        ))
+            .bind(&filter.author)
+            .bind(&filter.tags.tags)
+            .bind(filter.tags.all)
+            .fetch_one(&*self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    async fn authors(&self) -> Result<Vec<Author>, StoreError> {
+        Ok(sqlx::query_as::<_, Author>(r#"
+            SELECT authors.id, authors.name,
+                COUNT(quotes.id) AS quote_count,
+                MAX(quotes.created_at) AS latest_quote_at
+            FROM authors
+                LEFT JOIN quotes ON quotes.author_id = authors.id AND quotes.deleted_at IS NULL
+            GROUP BY authors.id
+            ORDER BY authors.name COLLATE "C", authors.id
+        "#)
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn author_quotes(&self, author_id: Uuid, offset: u64, limit: u64) -> Result<Vec<Quote>, StoreError> {
+        sqlx::query("SELECT 1 FROM authors WHERE id = $1")
+            .bind(author_id)
+            .fetch_one(&*self.pool)
+            .await?;
+
+        Ok(sqlx::query_as::<_, Quote>(&format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE author_id = $1 AND deleted_at IS NULL ORDER BY created_at, id LIMIT $2 OFFSET $3"))
+            .bind(author_id)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn tags(&self) -> Result<Vec<Tag>, StoreError> {
+        Ok(sqlx::query_as::<_, Tag>(r#"
+            SELECT tags.name, COUNT(*) AS quote_count
+            FROM tags
+                JOIN quote_tags ON quote_tags.tag_id = tags.id
+                JOIN quotes ON quotes.id = quote_tags.quote_id AND quotes.deleted_at IS NULL
+            GROUP BY tags.name
+            ORDER BY tags.name COLLATE "C"
+        "#)
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter, offset: u64, limit: u64) -> Result<Vec<AuditEntry>, StoreError> {
+        Ok(sqlx::query_as::<_, AuditEntry>(r#"
+            SELECT * FROM quote_audit
+            WHERE ($1::UUID IS NULL OR quote_id = $1)
+                AND ($2::TEXT IS NULL OR actor = $2)
+                AND ($3::TIMESTAMPTZ IS NULL OR at >= $3)
+                AND ($4::TIMESTAMPTZ IS NULL OR at < $4)
+            ORDER BY at DESC, id DESC
+            LIMIT $5 OFFSET $6
+        "#)
+            .bind(filter.quote_id)
+            .bind(&filter.actor)
+            .bind(filter.from)
+            .bind(filter.to)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn search(&self, query: &SearchQuery, offset: u64, limit: u64) -> Result<Vec<QuoteHit>, StoreError> {
+        // the parser would take anything after a `<` for a tag and drop it from the headline,
+        // so `<` is swapped for a control character, which quotes cannot contain, meanwhile
+        Ok(sqlx::query_as::<_, QuoteHit>(&format!(r#"
+            SELECT id, author, author_id, quote, created_at, version,
+                {QUOTE_TAGS_SQL} AS tags,
+                ts_rank(search, query) AS rank,
+                replace(ts_headline('english', replace(quote, '<', chr(1)), query, $7), chr(1), '<') AS snippet
+            FROM quotes, websearch_to_tsquery('english', $1) query
+            WHERE search @@ query AND deleted_at IS NULL
+                AND ($2::TEXT IS NULL OR position(lower($2) IN lower(author)) > 0)
+                AND {}
+            ORDER BY rank DESC, created_at DESC
+            LIMIT $3 OFFSET $4
+        "#, tag_filter_sql(5, 6)))
+            .bind(&query.text)
+            .bind(&query.author)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .bind(&query.tags.tags)
+            .bind(query.tags.all)
+            .bind(format!("StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_END}"))
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn import<Q>(&self, quotes: Q, dry_run: bool) -> Result<u64, StoreError>
+    where
+        Q: Stream<Item = Result<ImportedQuote, StoreError>> + Send,
+    {
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        let mut batches = std::pin::pin!(quotes.try_chunks(IMPORT_BATCH_SIZE));
+        while let Some(batch) = batches.try_next().await.map_err(|e| e.1)? {
+            inserted += Self::insert_batch(&mut tx, &batch).await?;
+        };
+
+        if !dry_run {
+            tx.commit().await?;
+        };
+        // otherwise the transaction rolls back on drop
+        Ok(inserted)
+    }
+
+    fn export(&self) -> impl Stream<Item = Result<Quote, StoreError>> + Send + 'static {
+        let pool = self.pool.clone();
+        async_stream::try_stream! {
+            let sql = format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE deleted_at IS NULL ORDER BY created_at, id");
+            let mut quotes = sqlx::query_as::<_, Quote>(&sql)
+                .fetch(&*pool);
+            while let Some(q) = quotes.try_next().await? {
+                yield q;
+            };
+        }
+    }
+
+    async fn trash(&self) -> Result<Vec<Quote>, StoreError> {
+        Ok(sqlx::query_as::<_, Quote>(&format!("SELECT quotes.*, {QUOTE_TAGS_SQL} AS tags FROM quotes WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id"))
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn restore(&self, id: Uuid) -> Result<Quote, StoreError> {
+        Ok(sqlx::query_as::<_, Quote>(&format!("UPDATE quotes SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING quotes.*, {QUOTE_TAGS_SQL} AS tags"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await?)
+    }
+
+    async fn purge_trash(&self, retention: Duration) -> Result<u64, StoreError> {
+        Ok(sqlx::query("DELETE FROM quotes WHERE deleted_at < CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'")
+            .bind(retention.as_secs_f64())
+            .execute(&*self.pool)
+            .await?
+            .rows_affected())
+    }
+
+    async fn purge_changes(&self, retention: Duration) -> Result<u64, StoreError> {
+        Ok(sqlx::query("DELETE FROM quote_changes WHERE changed_at < CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'")
+            .bind(retention.as_secs_f64())
+            .execute(&*self.pool)
+            .await?
+            .rows_affected())
+    }
+
+    async fn changes_since(&self, seq: i64) -> Result<Vec<QuoteChange>, StoreError> {
+        Ok(sqlx::query_as::<_, QuoteChange>("SELECT * FROM quote_changes WHERE seq > $1 ORDER BY seq")
+            .bind(seq)
+            .fetch_all(&*self.pool)
+            .await?)
+    }
+
+    async fn last_change_seq(&self) -> Result<i64, StoreError> {
+        Ok(sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(seq), 0) FROM quote_changes")
+            .fetch_one(&*self.pool)
+            .await?)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<QuoteChange> {
+        self.changes.subscribe()
+    }
+}
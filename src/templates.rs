@@ -0,0 +1,62 @@
+use askama::Template;
+use axum::{
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::store::{HIGHLIGHT_END, HIGHLIGHT_START};
+
+/// Render a template, failing with a 500
+pub fn render(template: &impl Template) -> Result<String, StatusCode> {
+    template.render().map_err(|e| {
+        tracing::error!(error = %e, "failed to render template");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// HTML response rendered from a template in `templates/`
+pub struct HtmlTemplate<T>(pub T);
+
+impl<T: Template> IntoResponse for HtmlTemplate<T> {
+    fn into_response(self) -> Response {
+        match render(&self.0) {
+            Ok(html) => Html(html).into_response(),
+            Err(status) => status.into_response(),
+        }
+    }
+}
+
+/// Escape text for use in HTML
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        };
+    };
+    escaped
+}
+
+/// HTML of a search snippet: the text escaped, with highlighted matches wrapped in `<mark>`
+pub fn highlight_html(snippet: &str) -> String {
+    escape_html(snippet)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_END, "</mark>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_escaped_text() {
+        let snippet = format!("<b>{HIGHLIGHT_START}AT&T{HIGHLIGHT_END}</b> & \"{HIGHLIGHT_START}sleigh{HIGHLIGHT_END}\"");
+        assert_eq!(highlight_html(&snippet), "&lt;b&gt;<mark>AT&amp;T</mark>&lt;/b&gt; &amp; &quot;<mark>sleigh</mark>&quot;");
+        assert_eq!(highlight_html("no match"), "no match");
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Colour cycles of the Day 23 presents, e.g. in `themes.toml`:
+///
+/// ```toml
+/// default = "classic"
+///
+/// [[theme]]
+/// name = "classic"
+/// palette = [
+///     { name = "red", color = "red" },
+///     { name = "blue", color = "blue" },
+/// ]
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Themes {
+    /// Theme used when none is asked for, the first one if unset
+    default: Option<String>,
+    #[serde(rename = "theme")]
+    themes: Vec<Theme>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    /// Presents cycle through the palette in order, wrapping around
+    pub palette: Vec<Swatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Swatch {
+    /// CSS class of the present
+    pub name: String,
+    /// Any CSS colour
+    pub color: String,
+}
+
+/// Identifiers safe as CSS class names and in URLs
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl Default for Themes {
+    /// The original red, blue, purple cycle
+    fn default() -> Self {
+        let swatch = |name: &str| Swatch { name: name.to_owned(), color: name.to_owned() };
+        Self {
+            default: None,
+            themes: vec![Theme {
+                name: "classic".to_owned(),
+                palette: vec![swatch("red"), swatch("blue"), swatch("purple")],
+            }],
+        }
+    }
+}
+
+impl Themes {
+    /// Load themes from a TOML file, the classic theme alone if it does not exist
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let themes: Self = toml::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        themes.check()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(themes)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.themes.is_empty() {
+            return Err("no theme defined".to_owned());
+        };
+        for theme in &self.themes {
+            if !is_identifier(&theme.name) {
+                return Err(format!("invalid theme name {:?}", theme.name));
+            };
+            if theme.palette.is_empty() {
+                return Err(format!("theme {} has an empty palette", theme.name));
+            };
+            for swatch in &theme.palette {
+                if !is_identifier(&swatch.name) {
+                    return Err(format!("theme {}: invalid colour name {:?}", theme.name, swatch.name));
+                };
+                // keep the value inside its CSS declaration
+                if swatch.color.is_empty() || swatch.color.contains([';', '{', '}', '<', '>']) {
+                    return Err(format!("theme {}: invalid colour {:?}", theme.name, swatch.color));
+                };
+            };
+        };
+        if let Some(default) = &self.default {
+            if self.get(default).is_none() {
+                return Err(format!("default theme {} is not defined", default));
+            };
+        };
+        Ok(())
+    }
+
+    pub fn all(&self) -> &[Theme] {
+        &self.themes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Theme> {
+        self.themes.iter().find(|t| t.name == name)
+    }
+
+    pub fn default_theme(&self) -> &Theme {
+        self.default.as_deref()
+            .and_then(|name| self.get(name))
+            .unwrap_or(&self.themes[0])
+    }
+}
+
+impl Theme {
+    /// Colour after `name` in the cycle
+    pub fn next(&self, name: &str) -> Option<&Swatch> {
+        let i = self.palette.iter().position(|s| s.name == name)?;
+        Some(&self.palette[(i + 1) % self.palette.len()])
+    }
+
+    /// Rules colouring `.present.<name>` for every colour of the palette
+    pub fn css(&self) -> String {
+        self.palette.iter()
+            .map(|s| format!(".present.{} {{\n    background-color: {};\n}}\n", s.name, s.color))
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+use std::{collections::BTreeMap, fmt};
+
+use axum::{extract::Json, http::StatusCode, response::{IntoResponse, Response}};
+use serde::Serialize;
+
+/// Size limits of quote fields, counted in characters
+#[derive(Debug, Clone)]
+pub struct QuoteLimits {
+    pub max_author_len: usize,
+    pub max_quote_len: usize,
+    pub max_tags: usize,
+    pub max_tag_len: usize,
+}
+
+impl Default for QuoteLimits {
+    fn default() -> Self {
+        Self {
+            max_author_len: 256,
+            max_quote_len: 4096,
+            max_tags: 16,
+            max_tag_len: 64,
+        }
+    }
+}
+
+/// Error messages by field, rendered as `422 {"errors": {"field": ["message", ..]}}`
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationErrors {
+    errors: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        let messages = self.errors.entry(field.to_owned()).or_default();
+        let message = message.into();
+        if !messages.contains(&message) {
+            messages.push(message);
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Err(self)` if any error was recorded
+    pub fn check(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+/// One line, e.g. for the per-row errors of an import
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self.errors.iter()
+            .map(|(field, messages)| format!("`{}` {}", field, messages.join(", ")))
+            .collect();
+        write!(f, "{}", fields.join("; "))
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(self)).into_response()
+    }
+}
+
+/// Check a text field is non-empty once trimmed, short enough and free of control characters
+fn validate_text(errors: &mut ValidationErrors, field: &str, value: &str, max_len: usize, allow_newlines: bool) {
+    if value.trim().is_empty() {
+        errors.add(field, "must not be empty");
+    };
+    if value.chars().count() > max_len {
+        errors.add(field, format!("must be at most {} characters", max_len));
+    };
+    if value.chars().any(|c| c.is_control() && !(allow_newlines && matches!(c, '\n' | '\r' | '\t'))) {
+        errors.add(field, "must not contain control characters");
+    };
+}
+
+/// Validate the fields of a quote as they are about to be stored
+pub fn validate_quote(
+    limits: &QuoteLimits,
+    author: &str,
+    quote: &str,
+    tags: Option<&[String]>,
+) -> Result<(), ValidationErrors>
+{
+    let mut errors = ValidationErrors::default();
+
+    validate_text(&mut errors, "author", author, limits.max_author_len, false);
+    // quotes may span several lines
+    validate_text(&mut errors, "quote", quote, limits.max_quote_len, true);
+
+    if let Some(tags) = tags {
+        if tags.len() > limits.max_tags {
+            errors.add("tags", format!("must have at most {} tags", limits.max_tags));
+        };
+        for tag in tags {
+            validate_text(&mut errors, "tags", tag, limits.max_tag_len, false);
+        };
+    };
+
+    errors.check()
+}
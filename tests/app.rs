@@ -0,0 +1,136 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use tower::ServiceExt;
+
+use shuttlings_cch24::{app, config::Config, state::AppState};
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    // no refills while the test runs
+    config.milk.initial = 1;
+    config.milk.max = 1;
+    config.milk.interval_secs = 3600;
+    config
+}
+
+async fn send(app: &Router, method: Method, uri: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap();
+    respond(app, request).await
+}
+
+/// Post each `(name, content)` as a multipart field
+async fn upload(app: &Router, uri: &str, fields: &[(&str, &str)]) -> (StatusCode, String) {
+    const BOUNDARY: &str = "cch24-boundary";
+    let mut body = String::new();
+    for (name, content) in fields {
+        body += &format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"Cargo.lock\"\r\n\r\n{}\r\n", BOUNDARY, name, content);
+    };
+    body += &format!("--{}--\r\n", BOUNDARY);
+
+    let request = Request::post(uri)
+        .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+        .body(Body::from(body))
+        .unwrap();
+    respond(app, request).await
+}
+
+async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn instances_are_independent() {
+    let a = app(AppState::for_tests(test_config()));
+    let b = app(AppState::for_tests(test_config()));
+
+    let (_, empty_board) = send(&b, Method::GET, "/12/board").await;
+    let (status, _) = send(&a, Method::POST, "/12/place/cookie/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(send(&a, Method::GET, "/12/board").await.1, empty_board);
+    assert_eq!(send(&b, Method::GET, "/12/board").await.1, empty_board);
+
+    // without a JSON body the bucket is tapped
+    assert_eq!(send(&a, Method::POST, "/9/milk").await.0, StatusCode::OK);
+    assert_eq!(send(&a, Method::POST, "/9/milk").await.0, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(send(&b, Method::POST, "/9/milk").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn configured_seek_url_and_rng_seed() {
+    let configured = |seed| {
+        let mut config = test_config();
+        config.seek_url = "https://example.com/seek".to_owned();
+        config.rng_seed = seed;
+        app(AppState::for_tests(config))
+    };
+
+    let request = Request::get("/-1/seek").body(Body::empty()).unwrap();
+    let response = configured(7).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()["location"], "https://example.com/seek");
+
+    // the same seed draws the same boards, starting over on reset
+    let a = configured(7);
+    let b = configured(7);
+    let first = send(&a, Method::GET, "/12/random-board").await.1;
+    assert_eq!(send(&b, Method::GET, "/12/random-board").await.1, first);
+    assert_ne!(send(&app(AppState::for_tests(test_config())), Method::GET, "/12/random-board").await.1, first);
+    send(&a, Method::POST, "/12/reset").await;
+    assert_eq!(send(&a, Method::GET, "/12/random-board").await.1, first);
+}
+
+const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "heck"
+version = "0.4.2"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+#[tokio::test]
+async fn boots_from_the_checked_in_config() {
+    let config = Config::load("config.toml", |name| (name == "GIFT_SECRET").then(|| "hunter2".to_owned())).unwrap();
+    let app = app(AppState::for_tests(config));
+
+    assert_eq!(send(&app, Method::GET, "/").await, (StatusCode::OK, "Hello, bird!".to_owned()));
+    // the advisory database is not checked in, only audits are off
+    let (status, message) = upload(&app, "/23/lockfile/audit", &[("lockfile", LOCKFILE)]).await;
+    assert_eq!((status, message.as_str()), (StatusCode::SERVICE_UNAVAILABLE, "no advisory database is configured"));
+    assert_eq!(upload(&app, "/23/lockfile/report", &[("lockfile", LOCKFILE)]).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn audits_with_an_advisory_database() {
+    let mut config = test_config();
+    config.lockfile.advisory_db = "tests/fixtures/advisory-db".to_owned();
+    let app = app(AppState::for_tests(config));
+
+    let (status, audit) = upload(&app, "/23/lockfile/audit?format=json", &[("lockfile", LOCKFILE)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(audit.contains("RUSTSEC-2099-0001"));
+}
+
+#[tokio::test]
+async fn one_canvas_per_lockfile() {
+    let app = app(AppState::for_tests(test_config()));
+    let lockfile = |checksum: &str| format!("{}checksum = \"{}\"\n", LOCKFILE, checksum.repeat(32));
+    let (a, b) = (lockfile("11"), lockfile("22"));
+
+    let (status, html) = upload(&app, "/23/lockfile", &[("lockfile", &a), ("lockfile", &b)]).await;
+    assert_eq!(status, StatusCode::OK);
+    let canvases: Vec<&str> = html.split(r#"<div class="canvas">"#).skip(1).collect();
+    assert_eq!(canvases.len(), 2);
+    assert!(canvases[0].contains("background-color:#111111") && !canvases[0].contains("#222222"));
+    assert!(canvases[1].contains("background-color:#222222"));
+}